@@ -1,2 +1,4 @@
 pub mod coffee_api;
 pub mod post;
+pub mod response_cache;
+pub mod retry;
@@ -1,10 +1,13 @@
-use serde::Deserialize;
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
-pub enum Tag {
-    Category { category_id: usize },
-    Other,
+/// A post tag. Category tags carry an ID; most tags also have a name.
+#[derive(Debug, Default)]
+pub struct Tag {
+    pub category_id: Option<usize>,
+    pub name: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -19,4 +22,190 @@ pub struct Post {
     pub project_update_heading: String,
     pub tags: Vec<Tag>,
     pub share_urls: ShareUrls,
+    /// HTML body of the post
+    #[serde(default)]
+    pub project_update_content: Option<String>,
+    #[serde(default)]
+    pub project_update_image: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub publish_on: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Audience the post is published to, e.g. "public" or "members"
+    #[serde(default, deserialize_with = "deserialize_label")]
+    pub visibility: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    pub is_members_only: bool,
+}
+
+/// A post exactly as the API returned it. Kept in the cache so songs can be
+/// rebuilt when parsing improves, without fetching anything.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawPost {
+    pub id: usize,
+    pub json: String,
+}
+
+impl RawPost {
+    pub fn new(json: String) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct PostId {
+            id: usize,
+        }
+
+        let PostId { id } = serde_json::from_str(&json).context("Post has no ID")?;
+        Ok(Self { id, json })
+    }
+
+    pub fn parse(&self) -> Result<Post> {
+        serde_json::from_str(&self.json)
+            .with_context(|| format!("Failed to deserialize post {}", self.id))
+    }
+}
+
+impl<'de> Deserialize<'de> for Tag {
+    /// Accepts tag objects with any mix of `category_id` and a name, as well
+    /// as bare tag names. Anything else becomes an empty tag.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match Value::deserialize(deserializer)? {
+            Value::Object(tag) => Tag {
+                category_id: tag
+                    .get("category_id")
+                    .and_then(Value::as_u64)
+                    .and_then(|id| usize::try_from(id).ok()),
+                name: ["name", "category_name", "title"]
+                    .iter()
+                    .find_map(|key| tag.get(*key).and_then(Value::as_str))
+                    .map(ToOwned::to_owned),
+            },
+            Value::String(name) => Tag {
+                category_id: None,
+                name: Some(name),
+            },
+            _ => Tag::default(),
+        })
+    }
+}
+
+impl Post {
+    pub fn members_only(&self) -> bool {
+        self.is_members_only
+            || self
+                .visibility
+                .as_deref()
+                .is_some_and(|visibility| visibility.eq_ignore_ascii_case("members"))
+    }
+}
+
+/// Accepts RFC 3339 timestamps as well as the `YYYY-MM-DD HH:MM:SS` (UTC) form
+/// the API uses for some fields. Anything unparseable is treated as missing
+/// rather than failing the whole page.
+fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let Some(Value::String(value)) = Option::<Value>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+
+    Ok(DateTime::parse_from_rfc3339(&value)
+        .map(|date| date.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(&value, "%Y-%m-%d %H:%M:%S").map(|date| date.and_utc())
+        })
+        .ok())
+}
+
+/// Accepts either a string or a number, since enum-like fields aren't
+/// consistently typed.
+fn deserialize_label<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Option::<Value>::deserialize(deserializer)? {
+        Some(Value::String(value)) => Some(value),
+        Some(Value::Number(value)) => Some(value.to_string()),
+        _ => None,
+    })
+}
+
+/// Accepts `true`/`false` as well as `1`/`0`.
+fn deserialize_flag<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Option::<Value>::deserialize(deserializer)? {
+        Some(Value::Bool(value)) => value,
+        Some(Value::Number(value)) => value.as_u64().is_some_and(|value| value != 0),
+        Some(Value::String(value)) => value == "1" || value.eq_ignore_ascii_case("true"),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::PostJson;
+
+    use super::*;
+
+    #[test]
+    fn test_post_metadata() {
+        let post: Post = serde_json::from_value(
+            PostJson::new(1, "Everlong - Foo Fighters | #42")
+                .with("project_update_content", "<p>Hello</p>")
+                .with("project_update_image", "https://example.com/cover.png")
+                .with("publish_on", "2024-03-01 18:30:00")
+                .with("created_at", "2024-02-28T10:00:00.000000Z")
+                .with("updated_at", Value::Null)
+                .with("visibility", "members")
+                .with("is_members_only", 0)
+                .build(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            post.publish_on.unwrap().to_rfc3339(),
+            "2024-03-01T18:30:00+00:00"
+        );
+        assert_eq!(
+            post.created_at.unwrap().to_rfc3339(),
+            "2024-02-28T10:00:00+00:00"
+        );
+        assert!(post.updated_at.is_none());
+        assert!(post.members_only());
+    }
+
+    #[test]
+    fn test_post_without_metadata() {
+        let post: Post = serde_json::from_value(
+            PostJson::new(1, "Everlong - Foo Fighters | #42")
+                .with("publish_on", "next tuesday")
+                .build(),
+        )
+        .unwrap();
+
+        assert!(post.publish_on.is_none());
+        assert!(post.project_update_content.is_none());
+        assert!(!post.members_only());
+    }
+
+    #[test]
+    fn test_tags() {
+        let tags: Vec<Tag> = serde_json::from_value(serde_json::json!([
+            { "category_id": 174_260 },
+            { "category_id": 9, "name": "Punk" },
+            { "name": "Rock" },
+            "Live",
+            null,
+        ]))
+        .unwrap();
+
+        assert_eq!(tags[0].category_id, Some(174_260));
+        assert_eq!(tags[1].name.as_deref(), Some("Punk"));
+        assert_eq!(tags[2].category_id, None);
+        assert_eq!(tags[3].name.as_deref(), Some("Live"));
+        assert!(tags[4].category_id.is_none() && tags[4].name.is_none());
+    }
 }
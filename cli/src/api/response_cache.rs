@@ -0,0 +1,105 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+
+const RESPONSE_CACHE_FILENAME: &str = "http_cache.json";
+
+/// A response body alongside the validators the server sent with it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: String,
+}
+
+/// Responses keyed by URL, used to make conditional requests so repeated
+/// checks only download pages that actually changed.
+#[derive(Debug, Default)]
+pub struct ResponseCache {
+    path: PathBuf,
+    responses: HashMap<String, CachedResponse>,
+}
+
+impl ResponseCache {
+    /// Loads the cache from the data directory. A missing or unreadable file
+    /// just means every request goes out unconditionally.
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(RESPONSE_CACHE_FILENAME);
+
+        let responses = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        Self { path, responses }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let bytes = serde_json::to_vec(&self.responses)?;
+        storage::write_atomic(&self.path, &bytes).with_context(|| {
+            format!(
+                "Failed to save HTTP response cache: {}",
+                self.path.display()
+            )
+        })
+    }
+
+    pub fn get(&self, url: &str) -> Option<&CachedResponse> {
+        self.responses.get(url)
+    }
+
+    /// Remembers a response, as long as the server gave us some way to
+    /// validate it later.
+    pub fn insert(&mut self, url: &str, response: CachedResponse) {
+        if response.etag.is_some() || response.last_modified.is_some() {
+            self.responses.insert(url.to_owned(), response);
+        } else {
+            self.responses.remove(url);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support;
+
+    use super::*;
+
+    #[test]
+    fn test_only_validated_responses_are_kept() {
+        let dir = test_support::temp_dir();
+
+        let mut cache = ResponseCache::load(dir.path());
+        cache.insert(
+            "https://example.com/a",
+            CachedResponse {
+                etag: Some("\"abc\"".to_owned()),
+                last_modified: None,
+                body: "{}".to_owned(),
+            },
+        );
+        cache.insert(
+            "https://example.com/b",
+            CachedResponse {
+                etag: None,
+                last_modified: None,
+                body: "{}".to_owned(),
+            },
+        );
+        cache.save().unwrap();
+
+        let cache = ResponseCache::load(dir.path());
+        assert_eq!(
+            cache.get("https://example.com/a").unwrap().etag.as_deref(),
+            Some("\"abc\"")
+        );
+        assert!(cache.get("https://example.com/b").is_none());
+    }
+}
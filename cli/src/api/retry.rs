@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::config::RetryConfig;
+
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(config: &RetryConfig) -> Self {
+        Self {
+            max_attempts: config.max_attempts.max(1),
+            initial_backoff: Duration::from_millis(config.initial_backoff_ms),
+            max_backoff: Duration::from_millis(config.max_backoff_ms),
+        }
+    }
+
+    /// How long to wait before retrying after the given (1-based) failed
+    /// attempt. A server-provided `Retry-After` wins over exponential backoff,
+    /// but is still capped so a hostile value can't stall the run forever.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)));
+
+        retry_after.unwrap_or(backoff).min(self.max_backoff)
+    }
+}
+
+/// Parses a `Retry-After` header, which is either a number of seconds or an
+/// HTTP date.
+pub fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new(&RetryConfig {
+            max_attempts: 5,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 4_000,
+        })
+    }
+
+    #[test]
+    fn test_delay_backs_off_exponentially_up_to_cap() {
+        let policy = policy();
+        assert_eq!(policy.delay(1, None), Duration::from_millis(500));
+        assert_eq!(policy.delay(2, None), Duration::from_secs(1));
+        assert_eq!(policy.delay(3, None), Duration::from_secs(2));
+        assert_eq!(policy.delay(4, None), Duration::from_secs(4));
+        assert_eq!(policy.delay(30, None), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_delay_prefers_retry_after() {
+        let policy = policy();
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(3))),
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_mins(1))),
+            Duration::from_secs(4)
+        );
+    }
+
+    #[test]
+    fn test_parse_retry_after_seconds() {
+        assert_eq!(
+            parse_retry_after(" 90 ", Utc::now()),
+            Some(Duration::from_secs(90))
+        );
+    }
+
+    #[test]
+    fn test_parse_retry_after_http_date() {
+        let now = DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+}
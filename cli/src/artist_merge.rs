@@ -0,0 +1,191 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use serde::Serialize;
+
+use crate::{
+    collation::{self, LEADING_ARTICLES},
+    models::song::Song,
+};
+
+/// Spellings of what is probably one artist, e.g. `Bowling For Soup` and
+/// `Bowling for Soup`
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct ArtistMerge {
+    /// The spelling used by the most songs, which the others merge into
+    pub canonical: String,
+    pub variants: Vec<String>,
+}
+
+impl Display for ArtistMerge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ← {}", self.canonical, self.variants.join(", "))
+    }
+}
+
+/// Reduces an artist name to what's left after ignoring case, accents,
+/// punctuation, whitespace, `&` versus `and` and a leading article, so
+/// `The Jackson 5` and `Jackson-5` share a key.
+pub fn artist_key(name: &str) -> String {
+    let name = collation::fold(name).replace('&', " and ");
+    let words: Vec<_> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    match words.split_first() {
+        Some((first, rest)) if !rest.is_empty() && LEADING_ARTICLES.contains(first) => {
+            rest.concat()
+        }
+        _ => words.concat(),
+    }
+}
+
+/// How many edits two keys of this length may differ by and still be taken
+/// for the same artist. Short names are left alone, since one letter is
+/// often the whole difference between two bands.
+fn max_distance(key_length: usize) -> usize {
+    match key_length {
+        0..6 => 0,
+        6..12 => 1,
+        _ => 2,
+    }
+}
+
+fn is_near_duplicate(a: &str, b: &str) -> bool {
+    let digits = |key: &str| key.chars().filter(char::is_ascii_digit).collect::<String>();
+    let allowed = max_distance(a.chars().count().min(b.chars().count()));
+
+    // `Blink-182` and `Blink-183` would be one edit apart
+    allowed > 0 && digits(a) == digits(b) && strsim::levenshtein(a, b) <= allowed
+}
+
+fn find_root(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+
+    index
+}
+
+/// Finds artist names (including featured artists) that differ only by case,
+/// punctuation, articles or a small edit distance.
+pub fn find_artist_merges(songs: &[Song]) -> Vec<ArtistMerge> {
+    // Song count for every spelling, by key
+    let mut spellings: BTreeMap<String, BTreeMap<&str, usize>> = BTreeMap::new();
+    for song in songs {
+        for artist in std::iter::once(&song.artist).chain(&song.featured_artists) {
+            if artist.is_empty() {
+                continue;
+            }
+
+            *spellings
+                .entry(artist_key(artist))
+                .or_default()
+                .entry(artist)
+                .or_default() += 1;
+        }
+    }
+
+    let keys: Vec<_> = spellings.keys().collect();
+    let mut parents: Vec<_> = (0..keys.len()).collect();
+    for i in 0..keys.len() {
+        for j in i + 1..keys.len() {
+            if is_near_duplicate(keys[i], keys[j]) {
+                let (root_i, root_j) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                parents[root_j] = root_i;
+            }
+        }
+    }
+
+    let mut clusters: BTreeMap<usize, BTreeMap<&str, usize>> = BTreeMap::new();
+    for (index, counts) in spellings.values().enumerate() {
+        let root = find_root(&mut parents, index);
+        let cluster = clusters.entry(root).or_default();
+        for (&spelling, &count) in counts {
+            *cluster.entry(spelling).or_default() += count;
+        }
+    }
+
+    clusters
+        .into_values()
+        .filter(|cluster| cluster.len() > 1)
+        .map(|cluster| {
+            let canonical = cluster
+                .iter()
+                .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))
+                .map(|(&spelling, _)| spelling.to_owned())
+                .unwrap_or_default();
+            let variants = cluster
+                .into_keys()
+                .filter(|&spelling| spelling != canonical)
+                .map(ToOwned::to_owned)
+                .collect();
+
+            ArtistMerge {
+                canonical,
+                variants,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(artist: &str) -> Song {
+        Song {
+            artist: artist.to_owned(),
+            ..Song::default()
+        }
+    }
+
+    #[test]
+    fn test_artist_key() {
+        assert_eq!(artist_key("The Jackson 5"), "jackson5");
+        assert_eq!(artist_key("Jackson-5"), "jackson5");
+        assert_eq!(artist_key("Simon & Garfunkel"), "simonandgarfunkel");
+        assert_eq!(artist_key("The The"), "the");
+        assert_eq!(artist_key("A"), "a");
+    }
+
+    #[test]
+    fn test_find_artist_merges() {
+        let mut songs: Vec<_> = [
+            "Bowling For Soup",
+            "Bowling for Soup",
+            "Bowling for Soup",
+            "Jackson 5",
+            "The Jackson 5",
+            "Red Hot Chilli Peppers",
+            "Red Hot Chili Peppers",
+            "Blink-182",
+            "Blink-183",
+            "Muse",
+            "Muze",
+        ]
+        .into_iter()
+        .map(song)
+        .collect();
+        songs[0].featured_artists = vec!["The Jackson 5".to_owned()];
+
+        assert_eq!(
+            find_artist_merges(&songs),
+            vec![
+                ArtistMerge {
+                    canonical: "Bowling for Soup".to_owned(),
+                    variants: vec!["Bowling For Soup".to_owned()],
+                },
+                ArtistMerge {
+                    canonical: "The Jackson 5".to_owned(),
+                    variants: vec!["Jackson 5".to_owned()],
+                },
+                ArtistMerge {
+                    canonical: "Red Hot Chili Peppers".to_owned(),
+                    variants: vec!["Red Hot Chilli Peppers".to_owned()],
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use std::cmp::Ordering;
+
+use anyhow::{Context, Result};
+use icu_collator::{Collator, CollatorBorrowed, options::CollatorOptions};
+use icu_locale::Locale;
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
+
+/// Words dropped from the front of an artist name when sorting or comparing
+pub const LEADING_ARTICLES: [&str; 3] = ["the", "a", "an"];
+
+/// Letters that don't decompose into a base letter plus accents
+fn expand_letter(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ß' => "ss",
+        'æ' => "ae",
+        'œ' => "oe",
+        'ø' => "o",
+        'đ' | 'ð' => "d",
+        'ł' => "l",
+        'þ' => "th",
+        'ı' => "i",
+        _ => return None,
+    })
+}
+
+/// Folds `s` into the form used to compare names and titles: compatibility
+/// decomposed with accents dropped, lowercased, curly quotes and primes made
+/// straight, every dash a hyphen and runs of whitespace a single space. So
+/// `Beyoncé` matches `BEYONCE` and `Don’t Stop` matches `Don't Stop`.
+pub fn fold(s: &str) -> String {
+    let mut folded = String::with_capacity(s.len());
+
+    for c in s
+        .nfkd()
+        .filter(|&c| !is_combining_mark(c))
+        .flat_map(char::to_lowercase)
+    {
+        match c {
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' | '\u{00B4}'
+            | '\u{0060}' => folded.push('\''),
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' | '\u{00AB}'
+            | '\u{00BB}' => folded.push('"'),
+            '\u{2010}'..='\u{2015}' | '\u{2212}' | '\u{FE58}' => folded.push('-'),
+            c if c.is_whitespace() => {
+                if !folded.is_empty() && !folded.ends_with(' ') {
+                    folded.push(' ');
+                }
+            }
+            c => match expand_letter(c) {
+                Some(expansion) => folded.push_str(expansion),
+                None => folded.push(c),
+            },
+        }
+    }
+
+    folded.truncate(folded.trim_end().len());
+    folded
+}
+
+/// The words of `s` once folded, split at anything that isn't a letter or
+/// digit, e.g. `["jay", "z"]` for `Jay-Z`
+pub fn words(s: &str) -> Vec<String> {
+    fold(s)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// `name` without a leading "The", "A" or "An", e.g. `Beatles` for
+/// `The Beatles`. Names that are nothing but an article are left as they are.
+pub fn without_leading_article(name: &str) -> &str {
+    let name = name.trim_start();
+
+    match name.split_once(char::is_whitespace) {
+        Some((first, rest))
+            if !rest.trim().is_empty()
+                && LEADING_ARTICLES
+                    .iter()
+                    .any(|article| first.eq_ignore_ascii_case(article)) =>
+        {
+            rest.trim_start()
+        }
+        _ => name,
+    }
+}
+
+/// Alphabetical order for a language, e.g. Swedish putting `Ö` after `Z`
+/// where English sorts it with `O`. Case and accents only break ties between
+/// otherwise equal strings.
+pub struct Collation {
+    collator: CollatorBorrowed<'static>,
+}
+
+impl Collation {
+    /// Collation for a BCP 47 locale such as `sv-SE`, or the system's locale
+    /// when `None`. Languages without their own rules use the Unicode root
+    /// order.
+    pub fn new(locale: Option<&str>) -> Result<Self> {
+        let locale = match locale {
+            Some(locale) => locale
+                .parse()
+                .with_context(|| format!("Invalid locale: {locale}"))?,
+            None => system_locale().unwrap_or(Locale::UNKNOWN),
+        };
+
+        let collator = Collator::try_new((&locale).into(), CollatorOptions::default())
+            .with_context(|| format!("No collation data for locale {locale}"))?;
+        Ok(Self { collator })
+    }
+
+    /// Compares two strings in this order. Strings the collation sees as equal
+    /// are ordered by their composed form to keep the order stable.
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.collator
+            .compare(a, b)
+            .then_with(|| a.nfc().cmp(b.nfc()))
+    }
+}
+
+/// The locale the system is set to, e.g. `sv-SE` for `LANG=sv_SE.UTF-8`.
+/// `None` when it's unset, `C`/`POSIX` or can't be parsed.
+fn system_locale() -> Option<Locale> {
+    let locale = sys_locale::get_locale()?;
+    let locale = locale
+        .split(['.', '@'])
+        .next()
+        .unwrap_or_default()
+        .replace('_', "-");
+
+    if locale == "C" || locale == "POSIX" {
+        return None;
+    }
+    locale.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fold() {
+        assert_eq!(fold("Beyoncé"), fold("BEYONCE"));
+        assert_eq!(fold("Beyonce\u{301}"), "beyonce");
+        assert_eq!(fold("Don’t Stop Me Now"), "don't stop me now");
+        assert_eq!(fold("“Weird Al” Yankovic"), "\"weird al\" yankovic");
+        assert_eq!(
+            fold("Trans–Siberian  Orchestra "),
+            "trans-siberian orchestra"
+        );
+        assert_eq!(fold("Motörhead"), "motorhead");
+        assert_eq!(fold("Sigur Rós"), "sigur ros");
+        assert_eq!(fold("Die Ärzte"), "die arzte");
+        assert_eq!(fold("ﬁre"), "fire");
+        assert_eq!(fold("Mø"), "mo");
+        assert_eq!(fold("Кино"), "кино");
+    }
+
+    #[test]
+    fn test_without_leading_article() {
+        assert_eq!(without_leading_article("The Beatles"), "Beatles");
+        assert_eq!(
+            without_leading_article("a  Perfect Circle"),
+            "Perfect Circle"
+        );
+        assert_eq!(without_leading_article("The The"), "The");
+        assert_eq!(without_leading_article("The"), "The");
+        assert_eq!(
+            without_leading_article("Theory of a Deadman"),
+            "Theory of a Deadman"
+        );
+    }
+
+    #[test]
+    fn test_collation() {
+        let names = [
+            "Zedd",
+            "Ólafur Arnalds",
+            "abba",
+            "Öst",
+            "Björk",
+            "Beyoncé",
+            "Beyonce",
+        ];
+        let sorted = |locale| {
+            let collation = Collation::new(Some(locale)).unwrap();
+            let mut names = names.to_vec();
+            names.sort_by(|a, b| collation.compare(a, b));
+            names
+        };
+
+        assert_eq!(
+            sorted("en-US"),
+            vec![
+                "abba",
+                "Beyonce",
+                "Beyoncé",
+                "Björk",
+                "Ólafur Arnalds",
+                "Öst",
+                "Zedd"
+            ]
+        );
+        // Swedish has Ö as its own letter after Z
+        assert_eq!(
+            sorted("sv-SE"),
+            vec![
+                "abba",
+                "Beyonce",
+                "Beyoncé",
+                "Björk",
+                "Ólafur Arnalds",
+                "Zedd",
+                "Öst"
+            ]
+        );
+        assert!(Collation::new(Some("not a locale")).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::{models::song::Difficulty, storage};
+
+const CONFIG_FILENAME: &str = "config.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub api: ApiConfig,
+    pub difficulty: DifficultyConfig,
+    pub fetch: FetchConfig,
+    pub http: HttpConfig,
+    pub output: OutputConfig,
+    pub retry: RetryConfig,
+    pub storage: StorageConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    /// Scheme and host of the Buy Me a Coffee API, without a trailing path
+    pub base_url: String,
+    /// Creator slug whose posts are indexed
+    pub creator: String,
+    /// Post category holding the transcriptions
+    pub category_id: usize,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "https://app.buymeacoffee.com".to_owned(),
+            creator: "drumscribe".to_owned(),
+            category_id: 73_044,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DifficultyConfig {
+    /// Category IDs mapped to difficulties, on top of (and overriding) the
+    /// built-in mapping
+    pub categories: BTreeMap<usize, Difficulty>,
+    /// Categories that aren't difficulties, so they don't trigger warnings
+    pub ignored_categories: Vec<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FetchConfig {
+    /// Posts requested per page
+    pub page_size: usize,
+    /// Pages requested at once while building the full index
+    pub concurrency: usize,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            page_size: 20,
+            concurrency: 4,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Total time allowed for a single request, in seconds
+    pub timeout_secs: u64,
+    /// Time allowed for establishing a connection, in seconds
+    pub connect_timeout_secs: u64,
+    /// Proxy every request is sent through, e.g. `http://proxy.example.com:3128`.
+    /// When unset, the standard `HTTP_PROXY`/`HTTPS_PROXY` variables apply.
+    pub proxy: Option<String>,
+    /// `User-Agent` header sent with every request
+    pub user_agent: String,
+    /// PEM file of extra certificates to trust, e.g. a corporate root CA
+    pub ca_bundle: Option<PathBuf>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 30,
+            connect_timeout_secs: 10,
+            proxy: None,
+            user_agent: concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")).to_owned(),
+            ca_bundle: None,
+        }
+    }
+}
+
+/// How artist groups are ordered in the grouped outputs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArtistOrder {
+    /// By sort name, so `The Beatles` is listed under B
+    #[default]
+    SortName,
+    /// By the name as credited
+    Name,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub artist_order: ArtistOrder,
+    /// Language whose alphabetical order artists and titles are sorted in,
+    /// e.g. `sv-SE`; the system's when unset
+    pub locale: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    /// Attempts per page before giving up, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled after every failed attempt
+    pub initial_backoff_ms: u64,
+    /// Upper bound for any single delay, including `Retry-After` values
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 60_000,
+        }
+    }
+}
+
+/// Where the index is kept in the data directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum StorageBackend {
+    /// A single `index.bin` file
+    #[default]
+    File,
+    /// An `index.sqlite3` database with full-text search; needs a build with
+    /// the `sqlite` feature
+    Sqlite,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+}
+
+impl Config {
+    /// Loads `config.toml` from the data directory, falling back to defaults
+    /// when the file doesn't exist.
+    pub fn load(data_dir: &Path) -> Result<Self> {
+        let config_path = data_dir.join(CONFIG_FILENAME);
+
+        let Some(contents) = storage::read_optional(&config_path)? else {
+            return Ok(Config::default());
+        };
+
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file: {}", config_path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_api_config_keeps_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            [api]
+            base_url = "http://localhost:8080"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.api.base_url, "http://localhost:8080");
+        assert_eq!(config.api.creator, "drumscribe");
+        assert_eq!(config.api.category_id, 73_044);
+    }
+
+    #[test]
+    fn test_difficulty_categories() {
+        let config: Config = toml::from_str(
+            r#"
+            [difficulty.categories]
+            200001 = "Beginner"
+            200002 = "Master"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.difficulty.categories.get(&200_002),
+            Some(&Difficulty::Master)
+        );
+    }
+
+    #[test]
+    fn test_artist_order() {
+        let config: Config = toml::from_str("[output]\nartist_order = \"name\"").unwrap();
+        assert_eq!(config.output.artist_order, ArtistOrder::Name);
+        assert_eq!(Config::default().output.artist_order, ArtistOrder::SortName);
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let result: Result<Config, _> = toml::from_str("[api]\nbase_uri = \"x\"");
+        assert!(result.is_err());
+    }
+}
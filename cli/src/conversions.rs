@@ -1 +1,2 @@
+pub mod heading;
 pub mod post;
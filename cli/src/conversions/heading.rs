@@ -0,0 +1,426 @@
+use std::{str::FromStr, sync::LazyLock};
+
+use crate::models::{sequence_number::SequenceNumber, song::Confidence};
+use anyhow::Error;
+use regex::Regex;
+
+static SEQUENCE_NUMBER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(#)?\s*(\d+[A-Za-z]*)\b").unwrap());
+static TRAILING_SEQUENCE_NUMBER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s#(\d+[A-Za-z]*)\s*$").unwrap());
+static BRACKETED_FEATURE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\s*[(\[]\s*(?:feat\.|ft\.|featuring|feat|ft)\s+([^)\]]+)[)\]]").unwrap()
+});
+static TRAILING_FEATURE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\s+(?:feat\.|ft\.|featuring|feat|ft)\s+(.+)$").unwrap());
+/// Stricter than `TRAILING_FEATURE`, since titles use words like "Feat" and
+/// "Ft." on their own ("The Feat Of Clay", "Attack of the 50 Ft. Woman")
+static TRAILING_TITLE_FEATURE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s+(?:feat\.|Feat\.|ft\.|featuring)\s+(.+)$").unwrap());
+static ANNOTATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s*[(\[]([^)\]]*)[)\]]").unwrap());
+
+/// The parts of a post heading, e.g. `Everlong - Foo Fighters | #42`.
+///
+/// Besides the standard layout this understands titles containing ` - `, en
+/// and em dashes, sequence numbers without `#`, `feat.`/`ft.` credits and
+/// bracketed annotations such as `(Live)`.
+#[derive(Debug)]
+pub struct SongDetails {
+    pub artist: String,
+    pub featured: Vec<String>,
+    pub title: String,
+    pub sequence_number: Option<SequenceNumber>,
+    pub confidence: Confidence,
+}
+
+impl FromStr for SongDetails {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut confidence = Confidence::High;
+
+        let (main, sequence_number) = match top_level_position(s, |c| c == '|') {
+            Some(position) => {
+                let sequence_part = s[position + 1..].trim();
+                let sequence_number =
+                    SEQUENCE_NUMBER
+                        .captures(sequence_part)
+                        .and_then(|captures| {
+                            if captures.get(1).is_none() {
+                                confidence = confidence.min(Confidence::Medium);
+                            }
+                            captures[2].parse().ok()
+                        });
+
+                (&s[..position], sequence_number)
+            }
+            None => match TRAILING_SEQUENCE_NUMBER.captures(s) {
+                Some(captures) => {
+                    confidence = confidence.min(Confidence::Medium);
+                    (
+                        &s[..captures.get(0).unwrap().start()],
+                        captures[1].parse().ok(),
+                    )
+                }
+                None => (s, None),
+            },
+        };
+
+        let separators = dash_separators(main);
+        let (title, artist) = match separators.last() {
+            Some(&(start, end, dash)) => {
+                if separators.len() > 1 || dash != '-' {
+                    confidence = confidence.min(Confidence::Medium);
+                }
+                (&main[..start], &main[end..])
+            }
+            None => (main, ""),
+        };
+
+        let mut featured = vec![];
+        let mut title = extract_features(title, &TRAILING_TITLE_FEATURE, &mut featured);
+        let mut artist = extract_features(artist, &TRAILING_FEATURE, &mut featured);
+
+        // Annotations belong to the recording, not the artist
+        let annotations: Vec<_> = ANNOTATION
+            .captures_iter(&artist)
+            .map(|captures| captures[1].trim().to_owned())
+            .filter(|annotation| !annotation.is_empty())
+            .collect();
+        if !annotations.is_empty() {
+            confidence = confidence.min(Confidence::Medium);
+            artist = ANNOTATION.replace_all(&artist, "").into_owned();
+            title = format!("{} ({})", title.trim(), annotations.join(", "));
+        }
+
+        let artist = artist.trim().to_owned();
+        let title = title.trim().to_owned();
+        if artist.is_empty() || title.is_empty() || sequence_number.is_none() {
+            confidence = Confidence::Low;
+        }
+
+        Ok(Self {
+            artist,
+            featured,
+            title,
+            sequence_number,
+            confidence,
+        })
+    }
+}
+
+/// Byte offset of the first character matching `predicate` outside brackets.
+fn top_level_position(s: &str, predicate: impl Fn(char) -> bool) -> Option<usize> {
+    let mut depth = 0usize;
+
+    for (index, c) in s.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ if depth == 0 && predicate(c) => return Some(index),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Start, end and character of every dash that separates title from artist:
+/// a hyphen with whitespace before it and whitespace (or nothing) after it,
+/// or any en/em dash. Dashes inside brackets are ignored.
+fn dash_separators(s: &str) -> Vec<(usize, usize, char)> {
+    let mut separators = vec![];
+    let mut depth = 0usize;
+    let mut previous = None;
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            '-' | '–' | '—' if depth == 0 => {
+                let spaced = previous.is_some_and(char::is_whitespace)
+                    && chars.peek().is_none_or(|&(_, next)| next.is_whitespace());
+                if c != '-' || spaced {
+                    separators.push((index, index + c.len_utf8(), c));
+                }
+            }
+            _ => {}
+        }
+
+        previous = Some(c);
+    }
+
+    separators
+}
+
+/// Removes bracketed `feat.` credits and a trailing one matching `trailing`
+/// from `s`, collecting the names.
+fn extract_features(s: &str, trailing: &Regex, featured: &mut Vec<String>) -> String {
+    for captures in BRACKETED_FEATURE.captures_iter(s) {
+        featured.extend(split_credits(&captures[1]));
+    }
+
+    let mut s = BRACKETED_FEATURE.replace_all(s, "").into_owned();
+    if let Some(captures) = trailing.captures(&s) {
+        featured.extend(split_credits(&captures[1]));
+        s.truncate(captures.get(0).unwrap().start());
+    }
+
+    s
+}
+
+fn split_credits(credits: &str) -> impl Iterator<Item = String> + '_ {
+    credits
+        .split([',', '&'])
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(ToOwned::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Case {
+        heading: &'static str,
+        title: &'static str,
+        artist: &'static str,
+        featured: &'static [&'static str],
+        sequence_number: &'static str,
+        confidence: Confidence,
+    }
+
+    const CORPUS: &[Case] = &[
+        Case {
+            heading: "Everlong - Foo Fighters | #42 DRUMSCRIBE",
+            title: "Everlong",
+            artist: "Foo Fighters",
+            featured: &[],
+            sequence_number: "42",
+            confidence: Confidence::High,
+        },
+        Case {
+            heading: "  Everlong  -  Foo Fighters  |  #42  ",
+            title: "Everlong",
+            artist: "Foo Fighters",
+            featured: &[],
+            sequence_number: "42",
+            confidence: Confidence::High,
+        },
+        Case {
+            heading: "Everlong - Foo Fighters | ",
+            title: "Everlong",
+            artist: "Foo Fighters",
+            featured: &[],
+            sequence_number: "",
+            confidence: Confidence::Low,
+        },
+        Case {
+            heading: "Everlong - ",
+            title: "Everlong",
+            artist: "",
+            featured: &[],
+            sequence_number: "",
+            confidence: Confidence::Low,
+        },
+        Case {
+            heading: "Everlong",
+            title: "Everlong",
+            artist: "",
+            featured: &[],
+            sequence_number: "",
+            confidence: Confidence::Low,
+        },
+        Case {
+            heading: "Everlong - Foo Fighters | #42 | DRUMSCRIBE",
+            title: "Everlong",
+            artist: "Foo Fighters",
+            featured: &[],
+            sequence_number: "42",
+            confidence: Confidence::High,
+        },
+        Case {
+            heading: "Everlong - Foo Fighters | 42",
+            title: "Everlong",
+            artist: "Foo Fighters",
+            featured: &[],
+            sequence_number: "42",
+            confidence: Confidence::Medium,
+        },
+        Case {
+            heading: "This Christmas Day - Trans-Siberian Orchestra | #622",
+            title: "This Christmas Day",
+            artist: "Trans-Siberian Orchestra",
+            featured: &[],
+            sequence_number: "622",
+            confidence: Confidence::High,
+        },
+        Case {
+            heading: "Bohemian Rhapsody - Remastered 2011 - Queen | #7",
+            title: "Bohemian Rhapsody - Remastered 2011",
+            artist: "Queen",
+            featured: &[],
+            sequence_number: "7",
+            confidence: Confidence::Medium,
+        },
+        Case {
+            heading: "Everlong – Foo Fighters | #42",
+            title: "Everlong",
+            artist: "Foo Fighters",
+            featured: &[],
+            sequence_number: "42",
+            confidence: Confidence::Medium,
+        },
+        Case {
+            heading: "Everlong—Foo Fighters | #42",
+            title: "Everlong",
+            artist: "Foo Fighters",
+            featured: &[],
+            sequence_number: "42",
+            confidence: Confidence::Medium,
+        },
+        Case {
+            heading: "Everlong - Foo Fighters #42",
+            title: "Everlong",
+            artist: "Foo Fighters",
+            featured: &[],
+            sequence_number: "42",
+            confidence: Confidence::Medium,
+        },
+        Case {
+            heading: "Stan - Eminem feat. Dido | #101",
+            title: "Stan",
+            artist: "Eminem",
+            featured: &["Dido"],
+            sequence_number: "101",
+            confidence: Confidence::High,
+        },
+        Case {
+            heading: "Walk This Way - Run-DMC ft. Aerosmith | #55",
+            title: "Walk This Way",
+            artist: "Run-DMC",
+            featured: &["Aerosmith"],
+            sequence_number: "55",
+            confidence: Confidence::High,
+        },
+        Case {
+            heading: "Crazy in Love feat. Jay-Z - Beyoncé | #5",
+            title: "Crazy in Love",
+            artist: "Beyoncé",
+            featured: &["Jay-Z"],
+            sequence_number: "5",
+            confidence: Confidence::High,
+        },
+        Case {
+            heading: "Attack of the 50 Ft. Woman - The Cramps | #6",
+            title: "Attack of the 50 Ft. Woman",
+            artist: "The Cramps",
+            featured: &[],
+            sequence_number: "6",
+            confidence: Confidence::High,
+        },
+        Case {
+            heading: "The Feat Of Clay - Tool | #7",
+            title: "The Feat Of Clay",
+            artist: "Tool",
+            featured: &[],
+            sequence_number: "7",
+            confidence: Confidence::High,
+        },
+        Case {
+            heading: "Numb / Encore (feat. Jay-Z) - Linkin Park | #12",
+            title: "Numb / Encore",
+            artist: "Linkin Park",
+            featured: &["Jay-Z"],
+            sequence_number: "12",
+            confidence: Confidence::High,
+        },
+        Case {
+            heading: "Song - Artist (ft. One, Two & Three) | #3",
+            title: "Song",
+            artist: "Artist",
+            featured: &["One", "Two", "Three"],
+            sequence_number: "3",
+            confidence: Confidence::High,
+        },
+        Case {
+            heading: "Everlong (Live - Wembley) - Foo Fighters | #43",
+            title: "Everlong (Live - Wembley)",
+            artist: "Foo Fighters",
+            featured: &[],
+            sequence_number: "43",
+            confidence: Confidence::High,
+        },
+        Case {
+            heading: "Everlong - Foo Fighters [Acoustic] | #44",
+            title: "Everlong (Acoustic)",
+            artist: "Foo Fighters",
+            featured: &[],
+            sequence_number: "44",
+            confidence: Confidence::Medium,
+        },
+        Case {
+            heading: "Chop Suey! - System Of A Down | #9a",
+            title: "Chop Suey!",
+            artist: "System Of A Down",
+            featured: &[],
+            sequence_number: "9a",
+            confidence: Confidence::High,
+        },
+        Case {
+            heading: "Aerials - System Of A Down | #12AB",
+            title: "Aerials",
+            artist: "System Of A Down",
+            featured: &[],
+            sequence_number: "12ab",
+            confidence: Confidence::High,
+        },
+        Case {
+            heading: "Aerials - System Of A Down #12ab",
+            title: "Aerials",
+            artist: "System Of A Down",
+            featured: &[],
+            sequence_number: "12ab",
+            confidence: Confidence::Medium,
+        },
+        Case {
+            heading: "Soft Cell Tribute - Daft Punk | #8",
+            title: "Soft Cell Tribute",
+            artist: "Daft Punk",
+            featured: &[],
+            sequence_number: "8",
+            confidence: Confidence::High,
+        },
+    ];
+
+    #[test]
+    fn test_heading_corpus() {
+        for case in CORPUS {
+            let details: SongDetails = case.heading.parse().unwrap();
+
+            assert_eq!(details.title, case.title, "title of {:?}", case.heading);
+            assert_eq!(details.artist, case.artist, "artist of {:?}", case.heading);
+            assert_eq!(
+                details.featured, case.featured,
+                "featured artists of {:?}",
+                case.heading
+            );
+            assert_eq!(
+                details
+                    .sequence_number
+                    .map(|sequence_number| sequence_number.to_string())
+                    .unwrap_or_default(),
+                case.sequence_number,
+                "sequence number of {:?}",
+                case.heading
+            );
+            assert_eq!(
+                details.confidence, case.confidence,
+                "confidence of {:?}",
+                case.heading
+            );
+        }
+    }
+}
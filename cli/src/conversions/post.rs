@@ -1,149 +1,286 @@
-use std::str::FromStr;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    sync::{LazyLock, Mutex},
+};
 
-use anyhow::{Error, anyhow};
+use anyhow::Result;
+use regex::Regex;
 
 use crate::{
-    api::post::{Post as ApiPost, Tag},
-    corrections::correct_artist,
-    models::song::{Difficulty, Song},
+    api::post::{Post as ApiPost, RawPost},
+    config::Config,
+    conversions::heading::SongDetails,
+    corrections::Corrections,
+    models::song::{Difficulty, Song, Tag},
 };
 
-impl From<&Vec<Tag>> for Difficulty {
-    fn from(value: &Vec<Tag>) -> Self {
-        value
-            .iter()
-            .flat_map(|tag| match tag {
-                Tag::Category { category_id } => match category_id {
-                    174_260 => Ok(Difficulty::Beginner),
-                    174_255 => Ok(Difficulty::Intermediate),
-                    174_257 => Ok(Difficulty::Advanced),
-                    174_258 => Ok(Difficulty::Expert),
-                    174_259 => Ok(Difficulty::Master),
-                    _ => Err(anyhow!("Not a difficulty")),
-                },
-                Tag::Other => Err(anyhow!("Not a category")),
-            })
-            .next()
-            .unwrap_or(Difficulty::Unrated)
+/// Category IDs of Drumscribe's difficulty tags
+const DEFAULT_DIFFICULTY_CATEGORIES: [(usize, Difficulty); 5] = [
+    (174_260, Difficulty::Beginner),
+    (174_255, Difficulty::Intermediate),
+    (174_257, Difficulty::Advanced),
+    (174_258, Difficulty::Expert),
+    (174_259, Difficulty::Master),
+];
+
+static DIFFICULTY_WORD: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(beginner|intermediate|advanced|expert|master)\b").unwrap()
+});
+
+/// Everything besides the post itself that decides what song it becomes.
+#[derive(Debug)]
+pub struct ParseContext {
+    /// Difficulty of each difficulty category ID
+    difficulties: BTreeMap<usize, Difficulty>,
+    /// Categories known not to be difficulties, e.g. the transcription
+    /// category every post is in
+    ignored_categories: BTreeSet<usize>,
+    corrections: Corrections,
+    /// Unknown category IDs already warned about during this run
+    warned_categories: Mutex<HashSet<usize>>,
+}
+
+impl Default for ParseContext {
+    fn default() -> Self {
+        Self::new(&Config::default(), Corrections::default())
     }
 }
 
-impl TryFrom<&ApiPost> for Song {
-    type Error = Error;
+impl ParseContext {
+    pub fn new(config: &Config, corrections: Corrections) -> Self {
+        let mut difficulties: BTreeMap<_, _> = DEFAULT_DIFFICULTY_CATEGORIES.into_iter().collect();
+        difficulties.extend(
+            config
+                .difficulty
+                .categories
+                .iter()
+                .map(|(&category_id, difficulty)| (category_id, difficulty.clone())),
+        );
+
+        Self {
+            difficulties,
+            ignored_categories: config
+                .difficulty
+                .ignored_categories
+                .iter()
+                .copied()
+                .chain([config.api.category_id])
+                .collect(),
+            corrections,
+            warned_categories: Mutex::default(),
+        }
+    }
+
+    /// Changes whenever the settings would turn the same post into a
+    /// different song, so the cache knows when to reparse. Hashes the
+    /// settings' JSON (every map in them is ordered) with FNV-1a, so the value
+    /// stays the same across builds and toolchains.
+    pub fn fingerprint(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0100_0000_01b3;
 
-    fn try_from(value: &ApiPost) -> Result<Self, Self::Error> {
-        let song_details: SongDetails = value.project_update_heading.parse()?;
+        let settings = serde_json::to_vec(&(
+            &self.difficulties,
+            &self.ignored_categories,
+            &self.corrections,
+        ))
+        .expect("parse settings serialize to JSON");
 
-        Ok(Song {
-            id: value.id,
+        settings.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+        })
+    }
+
+    pub fn corrections(&self) -> &Corrections {
+        &self.corrections
+    }
+
+    pub fn parse(&self, post: &ApiPost) -> Result<Song> {
+        let song_details: SongDetails = post.project_update_heading.parse()?;
+
+        let mut song = Song {
+            id: post.id,
             artist: song_details.artist,
+            featured_artists: song_details.featured,
             title: song_details.title,
             sequence_number: song_details.sequence_number,
-            link: value.share_urls.copy_url.clone(),
-            difficulty: (&value.tags).into(),
-        })
+            link: post.share_urls.copy_url.clone(),
+            difficulty: self.difficulty(post),
+            published_at: post.publish_on.or(post.created_at),
+            updated_at: post.updated_at,
+            body: post
+                .project_update_content
+                .as_deref()
+                .map(html_to_text)
+                .unwrap_or_default(),
+            cover_image: post.project_update_image.clone(),
+            visibility: post.visibility.clone(),
+            members_only: post.members_only(),
+            tags: post
+                .tags
+                .iter()
+                .filter(|tag| tag.category_id.is_some() || tag.name.is_some())
+                .map(|tag| Tag {
+                    id: tag.category_id,
+                    name: tag.name.clone(),
+                })
+                .collect(),
+            parse_confidence: song_details.confidence,
+        };
+        self.corrections.apply(&mut song);
+
+        Ok(song)
     }
-}
 
-struct SongDetails {
-    artist: String,
-    title: String,
-    sequence_number: String,
-}
+    pub fn parse_raw(&self, post: &RawPost) -> Result<Song> {
+        self.parse(&post.parse()?)
+    }
 
-impl FromStr for SongDetails {
-    type Err = Error;
+    /// Looks the difficulty up by category ID, falling back to a difficulty
+    /// named in a tag or in the part of the heading after the title and
+    /// artist (e.g. `| #42 | Advanced`).
+    fn difficulty(&self, post: &ApiPost) -> Difficulty {
+        let mut by_category = None;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (title, remainder) = s.split_once(" - ").unwrap_or((s, ""));
-        let (artist, sequence_part) = remainder.split_once(" | ").unwrap_or((remainder, ""));
+        for category_id in post.tags.iter().filter_map(|tag| tag.category_id) {
+            if let Some(difficulty) = self.difficulties.get(&category_id) {
+                by_category.get_or_insert_with(|| difficulty.clone());
+            } else if !self.ignored_categories.contains(&category_id) {
+                self.warn_unknown_category(post.id, category_id);
+            }
+        }
+
+        by_category
+            .or_else(|| {
+                post.tags
+                    .iter()
+                    .filter_map(|tag| tag.name.as_deref())
+                    .find_map(difficulty_in_text)
+            })
+            .or_else(|| {
+                post.project_update_heading
+                    .split_once('|')
+                    .and_then(|(_, tail)| difficulty_in_text(tail))
+            })
+            .unwrap_or(Difficulty::Unrated)
+    }
 
-        let sequence_number = sequence_part
-            .trim()
-            .strip_prefix('#')
-            .and_then(|s| s.split_whitespace().next())
-            .unwrap_or("");
+    fn warn_unknown_category(&self, post_id: usize, category_id: usize) {
+        let Ok(mut warned_categories) = self.warned_categories.lock() else {
+            return;
+        };
 
-        Ok(Self {
-            artist: correct_artist(artist.trim()).to_owned(),
-            title: title.trim().to_owned(),
-            sequence_number: sequence_number.to_owned(),
-        })
+        if warned_categories.insert(category_id) {
+            eprintln!(
+                "Warning: post {post_id} has unknown category ID {category_id}; map it under \
+                 [difficulty.categories] or list it in difficulty.ignored_categories in config.toml"
+            );
+        }
     }
 }
 
+fn difficulty_in_text(text: &str) -> Option<Difficulty> {
+    let word = DIFFICULTY_WORD.captures(text)?[1].to_lowercase();
+
+    Some(match word.as_str() {
+        "beginner" => Difficulty::Beginner,
+        "intermediate" => Difficulty::Intermediate,
+        "advanced" => Difficulty::Advanced,
+        "expert" => Difficulty::Expert,
+        _ => Difficulty::Master,
+    })
+}
+
+static HTML_TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+
+/// Reduces a post's HTML body to plain text.
+fn html_to_text(html: &str) -> String {
+    let text = HTML_TAG
+        .replace_all(html, " ")
+        .replace("&nbsp;", " ")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&");
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use crate::test_support::PostJson;
 
-    #[test]
-    fn test_song_details_full_format() {
-        // Format: "Title - Artist | #123"
-        let details: SongDetails = "Everlong - Foo Fighters | #42 DRUMSCRIBE".parse().unwrap();
-        assert_eq!(details.title, "Everlong");
-        assert_eq!(details.artist, "Foo Fighters");
-        assert_eq!(details.sequence_number, "42");
-    }
+    use super::*;
 
-    #[test]
-    fn test_song_details_with_whitespace() {
-        let details: SongDetails = "  Everlong  -  Foo Fighters  |  #42  ".parse().unwrap();
-        assert_eq!(details.title, "Everlong");
-        assert_eq!(details.artist, "Foo Fighters");
-        assert_eq!(details.sequence_number, "42");
+    fn post(heading: &str, tags: &serde_json::Value) -> ApiPost {
+        serde_json::from_value(PostJson::new(1, heading).with("tags", tags.clone()).build())
+            .unwrap()
     }
 
     #[test]
-    fn test_song_details_no_sequence_number() {
-        let details: SongDetails = "Everlong - Foo Fighters | ".parse().unwrap();
-        assert_eq!(details.title, "Everlong");
-        assert_eq!(details.artist, "Foo Fighters");
-        assert_eq!(details.sequence_number, "");
-    }
+    fn test_difficulty() {
+        let mut config = Config::default();
+        config
+            .difficulty
+            .categories
+            .insert(200_001, Difficulty::Expert);
+        let context = ParseContext::new(&config, Corrections::default());
+        let difficulty = |heading, tags| context.parse(&post(heading, &tags)).unwrap().difficulty;
 
-    #[test]
-    fn test_song_details_no_artist_or_sequence() {
-        let details: SongDetails = "Everlong - ".parse().unwrap();
-        assert_eq!(details.title, "Everlong");
-        assert_eq!(details.artist, "");
-        assert_eq!(details.sequence_number, "");
+        let heading = "Everlong - Foo Fighters | #42";
+        assert_eq!(
+            difficulty(
+                heading,
+                serde_json::json!([{ "category_id": 73_044 }, { "category_id": 174_258 }])
+            ),
+            Difficulty::Expert
+        );
+        assert_eq!(
+            difficulty(heading, serde_json::json!([{ "category_id": 200_001 }])),
+            Difficulty::Expert
+        );
+        assert_eq!(
+            difficulty(
+                heading,
+                serde_json::json!([{ "category_id": 1, "name": "Advanced" }])
+            ),
+            Difficulty::Advanced
+        );
+        assert_eq!(
+            difficulty(
+                "Everlong - Foo Fighters | #42 | Beginner",
+                serde_json::json!([])
+            ),
+            Difficulty::Beginner
+        );
+        assert_eq!(
+            difficulty("Master of Puppets - Metallica | #7", serde_json::json!([])),
+            Difficulty::Unrated
+        );
     }
 
     #[test]
-    fn test_song_details_only_title() {
-        let details: SongDetails = "Everlong".parse().unwrap();
-        assert_eq!(details.title, "Everlong");
-        assert_eq!(details.artist, "");
-        assert_eq!(details.sequence_number, "");
-    }
+    fn test_fingerprint_is_stable() {
+        let context = ParseContext::default();
+        // Fixed value: a fingerprint that changes between builds or runs
+        // would reparse every cache for nothing. Changing the built-in
+        // settings is meant to change it.
+        assert_eq!(context.fingerprint(), 974_876_122_810_345_626);
 
-    #[test]
-    fn test_song_details_sequence_with_trailing_bar() {
-        // Sequence number should stop at first whitespace/non-digit
-        let details: SongDetails = "Everlong - Foo Fighters | #42 | DRUMSCRIBE"
-            .parse()
-            .unwrap();
-        assert_eq!(details.title, "Everlong");
-        assert_eq!(details.artist, "Foo Fighters");
-        assert_eq!(details.sequence_number, "42");
-    }
-
-    #[test]
-    fn test_song_details_no_hash_prefix() {
-        let details: SongDetails = "Everlong - Foo Fighters | 42".parse().unwrap();
-        assert_eq!(details.title, "Everlong");
-        assert_eq!(details.artist, "Foo Fighters");
-        assert_eq!(details.sequence_number, "");
+        let mut corrections = Corrections::default();
+        corrections
+            .titles
+            .insert("Everlong ".to_owned(), "Everlong".to_owned());
+        let corrected = ParseContext::new(&Config::default(), corrections);
+        assert_ne!(corrected.fingerprint(), context.fingerprint());
     }
 
     #[test]
-    fn test_song_details_with_hyphenated_artist_name() {
-        // Sequence number should stop at first whitespace/non-digit
-        let details: SongDetails = "This Christmas Day - Trans-Siberian Orchestra | #622"
-            .parse()
-            .unwrap();
-        assert_eq!(details.title, "This Christmas Day");
-        assert_eq!(details.artist, "Trans-Siberian Orchestra");
-        assert_eq!(details.sequence_number, "622");
+    fn test_html_to_text() {
+        assert_eq!(
+            html_to_text("<p>Tom &amp; snare</p>\n<p>Tab&nbsp;<b>included</b></p>"),
+            "Tom & snare Tab included"
+        );
     }
 }
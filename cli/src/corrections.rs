@@ -1,17 +1,405 @@
-pub fn correct_artist(artist: &str) -> &str {
-    match artist {
-        "Blink-182" => "blink-182",
-        "Born Of Osiris" => "Born of Osiris",
-        "Bowling For Soup" => "Bowling for Soup",
-        "Bullet For My Valentine" => "Bullet for My Valentine",
-        "Fountains Of Wayne" => "Fountains of Wayne",
-        "Jackson 5" => "The Jackson 5",
-        "Puddle Of Mudd" => "Puddle of Mudd",
-        "Rage Against The Machine" => "Rage Against the Machine",
-        "System Of A Down" => "System of a Down",
-        "The Presidents Of The United States Of America" => {
-            "The Presidents of the United States of America"
-        }
-        _ => artist,
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use toml_edit::DocumentMut;
+
+use crate::{
+    collation,
+    models::{
+        sequence_number::{self, SequenceNumber},
+        song::{Difficulty, Song, Tag},
+    },
+    storage,
+};
+
+const CORRECTIONS_FILENAME: &str = "corrections.toml";
+
+/// Artist names Drumscribe has posted inconsistently, mapped to the spelling
+/// we use. Entries in the corrections file are applied on top of these.
+const DEFAULT_ARTIST_ALIASES: [(&str, &str); 10] = [
+    ("Blink-182", "blink-182"),
+    ("Born Of Osiris", "Born of Osiris"),
+    ("Bowling For Soup", "Bowling for Soup"),
+    ("Bullet For My Valentine", "Bullet for My Valentine"),
+    ("Fountains Of Wayne", "Fountains of Wayne"),
+    ("Jackson 5", "The Jackson 5"),
+    ("Puddle Of Mudd", "Puddle of Mudd"),
+    ("Rage Against The Machine", "Rage Against the Machine"),
+    ("System Of A Down", "System of a Down"),
+    (
+        "The Presidents Of The United States Of America",
+        "The Presidents of the United States of America",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleField {
+    Artist,
+    Title,
+}
+
+/// A regex replacement applied to every song's artist (including featured
+/// artists) or title
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub field: RuleField,
+    pub pattern: String,
+    pub replacement: String,
+}
+
+/// Replacement values for any of a single song's fields
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SongOverride {
+    pub artist: Option<String>,
+    pub featured_artists: Option<Vec<String>>,
+    pub title: Option<String>,
+    #[serde(with = "sequence_number::optional")]
+    pub sequence_number: Option<SequenceNumber>,
+    pub difficulty: Option<Difficulty>,
+    pub link: Option<String>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub body: Option<String>,
+    pub cover_image: Option<String>,
+    pub visibility: Option<String>,
+    pub members_only: Option<bool>,
+    pub tags: Option<Vec<Tag>>,
+}
+
+/// Fixes for what the parser gets wrong, read from `corrections.toml` in the
+/// data directory.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Corrections {
+    /// Artist names replaced by another spelling, e.g. `"Jackson 5" = "The Jackson 5"`
+    pub artists: BTreeMap<String, String>,
+    /// Titles replaced by another spelling
+    pub titles: BTreeMap<String, String>,
+    pub rules: Vec<Rule>,
+    /// Names artists are sorted under, e.g. `"Weird Al Yankovic" = "Yankovic"`,
+    /// instead of the name without its leading article
+    pub sort_names: BTreeMap<String, String>,
+    /// Overrides keyed by post ID
+    pub posts: BTreeMap<usize, SongOverride>,
+    #[serde(skip)]
+    compiled_rules: Vec<(RuleField, Regex)>,
+}
+
+impl Default for Corrections {
+    fn default() -> Self {
+        Self {
+            artists: DEFAULT_ARTIST_ALIASES
+                .iter()
+                .map(|&(from, to)| (from.to_owned(), to.to_owned()))
+                .collect(),
+            titles: BTreeMap::new(),
+            rules: vec![],
+            sort_names: BTreeMap::new(),
+            posts: BTreeMap::new(),
+            compiled_rules: vec![],
+        }
+    }
+}
+
+impl Corrections {
+    pub fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join(CORRECTIONS_FILENAME)
+    }
+
+    /// Loads `corrections.toml` from the data directory on top of the
+    /// built-in artist aliases.
+    pub fn load(data_dir: &Path) -> Result<Self> {
+        let path = Self::path(data_dir);
+
+        let Some(contents) = storage::read_optional(&path)? else {
+            return Ok(Corrections::default());
+        };
+
+        Self::parse(&contents)
+            .with_context(|| format!("Failed to load corrections file: {}", path.display()))
+    }
+
+    /// Adds `from = to` entries to the `[artists]` table of the corrections
+    /// file, keeping everything else in it as written. Existing entries that
+    /// pointed at a renamed spelling are pointed at its replacement instead.
+    pub fn add_artist_aliases<'a>(
+        data_dir: &Path,
+        aliases: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<()> {
+        let path = Self::path(data_dir);
+        let contents = storage::read_optional(&path)?.unwrap_or_default();
+        let mut document: DocumentMut = contents
+            .parse()
+            .with_context(|| format!("Failed to load corrections file: {}", path.display()))?;
+
+        let artists = document
+            .entry("artists")
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+            .context("`artists` in the corrections file is not a table")?;
+
+        // Replace values in place so their comments and spacing stay
+        let set = |item: &mut toml_edit::Item, to: &str| {
+            if let Some(value) = item.as_value_mut() {
+                let decor = value.decor().clone();
+                *value = to.into();
+                *value.decor_mut() = decor;
+            }
+        };
+        for (from, to) in aliases {
+            for (_, target) in artists.iter_mut() {
+                if target.as_str() == Some(from) {
+                    set(target, to);
+                }
+            }
+            match artists.get_mut(from) {
+                Some(existing) if existing.is_value() => set(existing, to),
+                _ => {
+                    artists.insert(from, toml_edit::value(to));
+                }
+            }
+        }
+
+        storage::write_atomic(&path, document.to_string().as_bytes())
+            .with_context(|| format!("Failed to write corrections file: {}", path.display()))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let file: Corrections = toml::from_str(contents)?;
+
+        let compiled_rules = file
+            .rules
+            .iter()
+            .map(|rule| {
+                Regex::new(&rule.pattern)
+                    .map(|regex| (rule.field, regex))
+                    .with_context(|| format!("Invalid pattern \"{}\"", rule.pattern))
+            })
+            .collect::<Result<_>>()?;
+
+        let mut corrections = Corrections::default();
+        corrections.artists.extend(file.artists);
+        corrections.titles = file.titles;
+        corrections.rules = file.rules;
+        corrections.sort_names = file.sort_names;
+        corrections.posts = file.posts;
+        corrections.compiled_rules = compiled_rules;
+
+        Ok(corrections)
+    }
+
+    pub fn correct_artist(&self, artist: &str) -> String {
+        let artist = self.apply_rules(RuleField::Artist, artist);
+        self.artists.get(&artist).cloned().unwrap_or(artist)
+    }
+
+    pub fn correct_title(&self, title: &str) -> String {
+        let title = self.apply_rules(RuleField::Title, title);
+        self.titles.get(&title).cloned().unwrap_or(title)
+    }
+
+    /// Name `artist` is sorted under when ordering by sort name.
+    pub fn sort_name(&self, artist: &str) -> String {
+        self.sort_names
+            .get(artist)
+            .cloned()
+            .unwrap_or_else(|| collation::without_leading_article(artist).to_owned())
+    }
+
+    fn apply_rules(&self, field: RuleField, value: &str) -> String {
+        self.rules
+            .iter()
+            .zip(&self.compiled_rules)
+            .filter(|(_, (rule_field, _))| *rule_field == field)
+            .fold(value.to_owned(), |value, (rule, (_, regex))| {
+                regex.replace_all(&value, &rule.replacement).into_owned()
+            })
+    }
+
+    /// Applies every correction to a parsed song, ending with the overrides
+    /// for its post.
+    pub fn apply(&self, song: &mut Song) {
+        song.artist = self.correct_artist(&song.artist);
+        song.featured_artists = song
+            .featured_artists
+            .iter()
+            .map(|artist| self.correct_artist(artist))
+            .collect();
+        song.title = self.correct_title(&song.title);
+
+        let Some(song_override) = self.posts.get(&song.id) else {
+            return;
+        };
+
+        macro_rules! override_fields {
+            ($($field:ident),*) => {
+                $(if let Some(value) = &song_override.$field {
+                    song.$field = value.clone();
+                })*
+            };
+        }
+        override_fields!(
+            artist,
+            featured_artists,
+            title,
+            difficulty,
+            link,
+            body,
+            members_only,
+            tags
+        );
+
+        if song_override.sequence_number.is_some() {
+            song.sequence_number
+                .clone_from(&song_override.sequence_number);
+        }
+        if song_override.published_at.is_some() {
+            song.published_at = song_override.published_at;
+        }
+        if song_override.updated_at.is_some() {
+            song.updated_at = song_override.updated_at;
+        }
+        if song_override.cover_image.is_some() {
+            song.cover_image.clone_from(&song_override.cover_image);
+        }
+        if song_override.visibility.is_some() {
+            song.visibility.clone_from(&song_override.visibility);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::test_support;
+
+    use super::*;
+
+    fn corrections(toml: &str) -> Corrections {
+        Corrections::parse(toml).unwrap()
+    }
+
+    #[test]
+    fn test_add_artist_aliases_keeps_the_file_as_written() {
+        let dir = test_support::temp_dir();
+        let path = Corrections::path(dir.path());
+        fs::write(
+            &path,
+            r#"# Fixes for the Drumscribe feed
+[artists]
+"Beyonce" = "Beyonce Knowles"   # accent dropped in older posts
+"Foo Fighter" = "Foo Fighters"
+
+# Typos
+[titles]
+"Chop Suey" = "Chop Suey!"
+"#,
+        )
+        .unwrap();
+
+        Corrections::add_artist_aliases(
+            dir.path(),
+            [("Beyonce Knowles", "Beyoncé"), ("Beyonce", "Beyoncé")],
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            r#"# Fixes for the Drumscribe feed
+[artists]
+"Beyonce" = "Beyoncé"   # accent dropped in older posts
+"Foo Fighter" = "Foo Fighters"
+"Beyonce Knowles" = "Beyoncé"
+
+# Typos
+[titles]
+"Chop Suey" = "Chop Suey!"
+"#
+        );
+        let corrections = Corrections::load(dir.path()).unwrap();
+        assert_eq!(corrections.correct_artist("Beyonce"), "Beyoncé");
+        assert_eq!(corrections.correct_artist("Beyonce Knowles"), "Beyoncé");
+        assert_eq!(corrections.correct_title("Chop Suey"), "Chop Suey!");
+    }
+
+    #[test]
+    fn test_builtin_aliases_are_defaults() {
+        let corrections = corrections(
+            r#"
+            [artists]
+            "Jackson 5" = "Jackson Five"
+            "#,
+        );
+
+        assert_eq!(corrections.correct_artist("Jackson 5"), "Jackson Five");
+        assert_eq!(corrections.correct_artist("Blink-182"), "blink-182");
+    }
+
+    #[test]
+    fn test_rules_titles_and_post_overrides() {
+        let corrections = corrections(
+            r#"
+            [titles]
+            "Chop Suey" = "Chop Suey!"
+
+            [[rules]]
+            field = "artist"
+            pattern = "^The (.+)$"
+            replacement = "$1, The"
+
+            [posts.7]
+            title = "Everlong (Acoustic)"
+            sequence_number = "42a"
+            difficulty = "Master"
+            "#,
+        );
+
+        let mut song = Song {
+            id: 7,
+            artist: "The Beatles".to_owned(),
+            featured_artists: vec!["The Roots".to_owned()],
+            title: "Everlong".to_owned(),
+            ..Song::default()
+        };
+        corrections.apply(&mut song);
+
+        assert_eq!(song.artist, "Beatles, The");
+        assert_eq!(song.featured_artists, vec!["Roots, The"]);
+        assert_eq!(song.title, "Everlong (Acoustic)");
+        assert_eq!(song.sequence_label(), "#42a");
+        assert_eq!(song.difficulty, Difficulty::Master);
+        assert_eq!(corrections.correct_title("Chop Suey"), "Chop Suey!");
+    }
+
+    #[test]
+    fn test_sort_names() {
+        let corrections = corrections(
+            r#"
+            [sort_names]
+            "Weird Al Yankovic" = "Yankovic"
+            "#,
+        );
+
+        assert_eq!(corrections.sort_name("The Beatles"), "Beatles");
+        assert_eq!(corrections.sort_name("Weird Al Yankovic"), "Yankovic");
+        assert_eq!(corrections.sort_name("Queen"), "Queen");
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_reported() {
+        let error =
+            Corrections::parse("[[rules]]\nfield = \"title\"\npattern = \"(\"\nreplacement = \"\"")
+                .unwrap_err();
+        assert!(format!("{error:#}").contains("Invalid pattern"));
     }
 }
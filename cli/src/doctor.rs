@@ -0,0 +1,292 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+};
+
+use serde::Serialize;
+
+use crate::{
+    artist_merge::{self, ArtistMerge},
+    collation,
+    index_cache::IndexCache,
+    models::{
+        sequence_number::SequenceNumber,
+        song::{Confidence, Song},
+    },
+};
+
+/// A post that couldn't be turned into a song at all
+#[derive(Debug, Serialize)]
+pub struct UnparsedPost {
+    pub id: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SongRef {
+    pub id: usize,
+    pub artist: String,
+    pub title: String,
+    pub sequence_number: Option<SequenceNumber>,
+    pub link: String,
+}
+
+impl From<&Song> for SongRef {
+    fn from(song: &Song) -> Self {
+        Self {
+            id: song.id,
+            artist: song.artist.clone(),
+            title: song.title.clone(),
+            sequence_number: song.sequence_number.clone(),
+            link: song.link.clone(),
+        }
+    }
+}
+
+impl Display for SongRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {} - {}", self.id, self.title, self.artist)?;
+        if let Some(sequence_number) = &self.sequence_number {
+            write!(f, " | #{sequence_number}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateSequenceNumber {
+    pub sequence_number: SequenceNumber,
+    pub songs: Vec<SongRef>,
+}
+
+/// Songs by the same artist with the same title; grouped output only keeps one
+#[derive(Debug, Serialize)]
+pub struct DuplicateTitle {
+    pub artist: String,
+    pub title: String,
+    pub songs: Vec<SongRef>,
+}
+
+/// Everything that makes songs disappear from, or show up oddly in, the
+/// grouped outputs.
+#[derive(Debug, Default, Serialize)]
+pub struct DoctorReport {
+    pub unparsed_posts: Vec<UnparsedPost>,
+    pub low_confidence: Vec<SongRef>,
+    pub missing_sequence_numbers: Vec<SongRef>,
+    pub missing_artists: Vec<SongRef>,
+    pub duplicate_sequence_numbers: Vec<DuplicateSequenceNumber>,
+    pub duplicate_titles: Vec<DuplicateTitle>,
+    /// Spellings of what is probably one artist
+    pub similar_artists: Vec<ArtistMerge>,
+}
+
+impl DoctorReport {
+    pub fn diagnose(index_cache: &IndexCache) -> Self {
+        let mut report = DoctorReport {
+            unparsed_posts: index_cache
+                .parse_failures()
+                .map(|(post, error)| UnparsedPost {
+                    id: post.id,
+                    error: format!("{error:#}"),
+                })
+                .collect(),
+            ..DoctorReport::default()
+        };
+
+        let mut by_sequence_number: BTreeMap<&SequenceNumber, Vec<&Song>> = BTreeMap::new();
+        let mut by_title: BTreeMap<(String, String), Vec<&Song>> = BTreeMap::new();
+
+        for song in &index_cache.songs {
+            if song.parse_confidence == Confidence::Low {
+                report.low_confidence.push(song.into());
+            }
+            if song.artist.is_empty() {
+                report.missing_artists.push(song.into());
+            }
+            let Some(sequence_number) = &song.sequence_number else {
+                report.missing_sequence_numbers.push(song.into());
+                continue;
+            };
+
+            by_sequence_number
+                .entry(sequence_number)
+                .or_default()
+                .push(song);
+            by_title
+                .entry((collation::fold(&song.artist), collation::fold(&song.title)))
+                .or_default()
+                .push(song);
+        }
+
+        report.duplicate_sequence_numbers = by_sequence_number
+            .into_iter()
+            .filter(|(_, songs)| songs.len() > 1)
+            .map(|(sequence_number, songs)| DuplicateSequenceNumber {
+                sequence_number: sequence_number.clone(),
+                songs: songs.into_iter().map(SongRef::from).collect(),
+            })
+            .collect();
+        report.duplicate_titles = by_title
+            .into_values()
+            .filter(|songs| songs.len() > 1)
+            .map(|songs| DuplicateTitle {
+                artist: songs[0].artist.clone(),
+                title: songs[0].title.clone(),
+                songs: songs.into_iter().map(SongRef::from).collect(),
+            })
+            .collect();
+        report.similar_artists = artist_merge::find_artist_merges(&index_cache.songs);
+
+        report
+    }
+
+    pub fn problem_count(&self) -> usize {
+        self.unparsed_posts.len()
+            + self.low_confidence.len()
+            + self.missing_sequence_numbers.len()
+            + self.missing_artists.len()
+            + self.duplicate_sequence_numbers.len()
+            + self.duplicate_titles.len()
+            + self.similar_artists.len()
+    }
+}
+
+fn write_section<T>(
+    f: &mut fmt::Formatter<'_>,
+    heading: &str,
+    items: &[T],
+    mut write_item: impl FnMut(&mut fmt::Formatter<'_>, &T) -> fmt::Result,
+) -> fmt::Result {
+    if items.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(f, "{heading} ({}):", items.len())?;
+    for item in items {
+        write_item(f, item)?;
+    }
+    writeln!(f)
+}
+
+impl Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_section(
+            f,
+            "Posts that failed to parse",
+            &self.unparsed_posts,
+            |f, post| writeln!(f, "  [{}] {}", post.id, post.error),
+        )?;
+        write_section(
+            f,
+            "Headings parsed with low confidence",
+            &self.low_confidence,
+            |f, song| writeln!(f, "  {song}"),
+        )?;
+        write_section(
+            f,
+            "Songs missing a sequence number (left out of grouped output)",
+            &self.missing_sequence_numbers,
+            |f, song| writeln!(f, "  {song}"),
+        )?;
+        write_section(
+            f,
+            "Songs missing an artist",
+            &self.missing_artists,
+            |f, song| writeln!(f, "  {song}"),
+        )?;
+        write_section(
+            f,
+            "Duplicate sequence numbers",
+            &self.duplicate_sequence_numbers,
+            |f, duplicate| {
+                writeln!(f, "  #{}", duplicate.sequence_number)?;
+                duplicate
+                    .songs
+                    .iter()
+                    .try_for_each(|song| writeln!(f, "    {song}"))
+            },
+        )?;
+        write_section(
+            f,
+            "Duplicate titles (only one is kept in grouped output)",
+            &self.duplicate_titles,
+            |f, duplicate| {
+                writeln!(f, "  {} - {}", duplicate.title, duplicate.artist)?;
+                duplicate
+                    .songs
+                    .iter()
+                    .try_for_each(|song| writeln!(f, "    {song}"))
+            },
+        )?;
+        write_section(
+            f,
+            "Artists that are probably the same (merge them with `merge-artists --apply`)",
+            &self.similar_artists,
+            |f, merge| writeln!(f, "  {merge}"),
+        )?;
+
+        match self.problem_count() {
+            0 => writeln!(f, "No problems found"),
+            1 => writeln!(f, "1 problem found"),
+            count => writeln!(f, "{count} problems found"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::post::RawPost;
+
+    use super::*;
+
+    fn song(id: usize, artist: &str, title: &str, sequence_number: &str) -> Song {
+        Song {
+            id,
+            artist: artist.to_owned(),
+            title: title.to_owned(),
+            sequence_number: sequence_number.parse().ok(),
+            parse_confidence: Confidence::High,
+            ..Song::default()
+        }
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let mut index_cache = IndexCache::default();
+        index_cache.songs = vec![
+            song(1, "Foo Fighters", "Everlong", "42"),
+            song(2, "foo fighters", "everlong", "43"),
+            song(3, "Queen", "Bohemian Rhapsody", "42"),
+            song(4, "", "Untitled", "44"),
+            Song {
+                parse_confidence: Confidence::Low,
+                ..song(5, "Muse", "Hysteria", "")
+            },
+            song(7, "", "Intro", ""),
+        ];
+        index_cache.posts = vec![RawPost::new(r#"{"id": 6, "tags": []}"#.to_owned()).unwrap()];
+
+        let report = DoctorReport::diagnose(&index_cache);
+
+        assert_eq!(report.unparsed_posts[0].id, 6);
+        assert_eq!(report.low_confidence[0].id, 5);
+        let ids = |songs: &[SongRef]| songs.iter().map(|song| song.id).collect::<Vec<_>>();
+        assert_eq!(ids(&report.missing_sequence_numbers), vec![5, 7]);
+        assert_eq!(ids(&report.missing_artists), vec![4, 7]);
+        assert_eq!(
+            report.duplicate_sequence_numbers[0].sequence_number,
+            SequenceNumber::from(42)
+        );
+        assert_eq!(report.duplicate_titles[0].songs.len(), 2);
+        assert_eq!(
+            report.similar_artists,
+            vec![ArtistMerge {
+                canonical: "Foo Fighters".to_owned(),
+                variants: vec!["foo fighters".to_owned()],
+            }]
+        );
+        assert_eq!(report.problem_count(), 9);
+    }
+}
@@ -0,0 +1,330 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::Path,
+};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    api::post::RawPost, corrections::Corrections, index_cache::IndexCache, models::song::Song,
+    storage,
+};
+
+/// Song IDs the macOS app marks as favorites, kept next to the cache
+const FAVORITES_FILENAME: &str = "favorites.json";
+/// Value of `format` in every export file
+const EXPORT_FORMAT: &str = "drumscribe-index-export";
+/// Version of the export layout; bump it for changes older versions can't read
+const EXPORT_VERSION: u32 = 1;
+
+/// Everything in the data directory worth moving to another machine, as
+/// written by `cache export`. The layout is documented in the README.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheExport {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub last_indexed: DateTime<Utc>,
+    /// Every post as the API returned it
+    pub posts: Vec<Value>,
+    /// Songs as parsed on the exporting machine, in the `--json` layout.
+    /// Importing rebuilds songs from `posts`, so these only matter for songs
+    /// cached before posts were stored.
+    pub songs: Vec<Song>,
+    pub favorites: BTreeSet<usize>,
+    /// Contents of `corrections.toml`, if there is one
+    pub corrections: Option<String>,
+}
+
+/// What `import` did, for reporting
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub posts_added: usize,
+    pub posts_replaced: usize,
+    pub favorites: usize,
+    /// Whether `corrections.toml` was written from the export
+    pub corrections_imported: bool,
+    /// Whether the export had corrections that were left out in favor of the
+    /// local file
+    pub corrections_kept: bool,
+}
+
+fn load_favorites(data_dir: &Path) -> Result<Option<BTreeSet<usize>>> {
+    let path = data_dir.join(FAVORITES_FILENAME);
+    let Some(contents) = storage::read_optional(&path)? else {
+        return Ok(None);
+    };
+
+    serde_json::from_str(&contents)
+        .map(Some)
+        .with_context(|| format!("Failed to read favorites: {}", path.display()))
+}
+
+/// When the post was last edited, for picking between two copies of it.
+fn updated_at(post: &RawPost) -> Option<DateTime<Utc>> {
+    post.parse().ok().and_then(|post| post.updated_at)
+}
+
+impl CacheExport {
+    /// Collects the cache and the user data kept next to it.
+    pub fn new(index_cache: &IndexCache, data_dir: &Path) -> Result<Self> {
+        let corrections_path = Corrections::path(data_dir);
+
+        Ok(Self {
+            format: EXPORT_FORMAT.to_owned(),
+            version: EXPORT_VERSION,
+            exported_at: Utc::now(),
+            last_indexed: index_cache.last_indexed,
+            posts: index_cache
+                .posts
+                .iter()
+                .map(|post| {
+                    serde_json::from_str(&post.json)
+                        .with_context(|| format!("Failed to read stored post {}", post.id))
+                })
+                .collect::<Result<_>>()?,
+            songs: index_cache.songs.clone(),
+            favorites: load_favorites(data_dir)?.unwrap_or_default(),
+            corrections: storage::read_optional(&corrections_path)?,
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        storage::write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct Header {
+            format: String,
+            version: u32,
+        }
+
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        // Check the header first so a newer layout gets a clear error rather
+        // than whatever field it changed
+        let header: Header = serde_json::from_str(&contents)
+            .with_context(|| format!("{} isn't a drumscribe-index export", path.display()))?;
+        if header.format != EXPORT_FORMAT {
+            bail!("{} isn't a drumscribe-index export", path.display());
+        }
+        if header.version > EXPORT_VERSION {
+            bail!(
+                "{} is export format v{}, written by a newer version of drumscribe-index (this version reads up to v{EXPORT_VERSION})",
+                path.display(),
+                header.version
+            );
+        }
+
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to read export: {}", path.display()))
+    }
+
+    /// Puts the export's posts, songs and user data into the cache and data
+    /// directory. Replaces what's there, or with `merge` combines the two by
+    /// post ID, keeping the more recently updated copy of posts in both and
+    /// the local `corrections.toml`. Songs still need a `reparse` afterwards.
+    pub fn import(
+        self,
+        index_cache: &mut IndexCache,
+        data_dir: &Path,
+        merge: bool,
+    ) -> Result<ImportSummary> {
+        // Check everything before changing anything
+        if let Some(corrections) = &self.corrections {
+            Corrections::parse(corrections).context("The exported corrections are invalid")?;
+        }
+        let mut summary = ImportSummary::default();
+        let posts = self
+            .posts
+            .into_iter()
+            .map(|post| RawPost::new(post.to_string()))
+            .collect::<Result<Vec<_>>>()?;
+
+        if merge {
+            let mut positions: HashMap<_, _> = index_cache
+                .posts
+                .iter()
+                .enumerate()
+                .map(|(position, post)| (post.id, position))
+                .collect();
+            for post in posts {
+                if let Some(&position) = positions.get(&post.id) {
+                    if updated_at(&post) > updated_at(&index_cache.posts[position]) {
+                        index_cache.posts[position] = post;
+                        summary.posts_replaced += 1;
+                    }
+                } else {
+                    positions.insert(post.id, index_cache.posts.len());
+                    index_cache.posts.push(post);
+                    summary.posts_added += 1;
+                }
+            }
+
+            let song_ids: BTreeSet<_> = index_cache.songs.iter().map(|song| song.id).collect();
+            index_cache.songs.extend(
+                self.songs
+                    .into_iter()
+                    .filter(|song| !song_ids.contains(&song.id)),
+            );
+            index_cache.last_indexed = index_cache.last_indexed.max(self.last_indexed);
+        } else {
+            summary.posts_added = posts.len();
+            index_cache.posts = posts;
+            index_cache.songs = self.songs;
+            index_cache.last_indexed = self.last_indexed;
+        }
+
+        let local_favorites = load_favorites(data_dir)?;
+        let favorites = match (merge, &local_favorites) {
+            (true, Some(local)) => local.union(&self.favorites).copied().collect(),
+            _ => self.favorites,
+        };
+        if local_favorites.is_some() || !favorites.is_empty() {
+            storage::write_atomic(
+                &data_dir.join(FAVORITES_FILENAME),
+                serde_json::to_string(&favorites)?.as_bytes(),
+            )?;
+        }
+        summary.favorites = favorites.len();
+
+        if let Some(corrections) = self.corrections {
+            let path = Corrections::path(data_dir);
+            if merge && path.exists() {
+                summary.corrections_kept = fs::read_to_string(&path)? != corrections;
+            } else {
+                storage::write_atomic(&path, corrections.as_bytes())?;
+                summary.corrections_imported = true;
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::test_support::{self, PostJson};
+
+    use super::*;
+
+    fn post(id: usize, heading: &str, updated_at: &str) -> Value {
+        PostJson::new(id, heading)
+            .with("updated_at", updated_at)
+            .build()
+    }
+
+    fn export(posts: Vec<Value>, favorites: &[usize]) -> CacheExport {
+        CacheExport {
+            format: EXPORT_FORMAT.to_owned(),
+            version: EXPORT_VERSION,
+            exported_at: Utc::now(),
+            last_indexed: Utc::now(),
+            posts,
+            songs: vec![],
+            favorites: favorites.iter().copied().collect(),
+            corrections: None,
+        }
+    }
+
+    #[test]
+    fn test_import_merges_by_post_id() {
+        let temp_dir = test_support::temp_dir();
+        let dir = temp_dir.path();
+        fs::write(dir.join(FAVORITES_FILENAME), "[1]").unwrap();
+
+        let mut index_cache = IndexCache::default();
+        index_cache.set_posts(
+            [
+                post(1, "Everlong - Foo Fighters | #1", "2024-01-01 00:00:00"),
+                post(2, "Halo - Beyonce | #2", "2024-01-01 00:00:00"),
+            ]
+            .iter()
+            .map(|post| RawPost::new(post.to_string()).unwrap())
+            .collect(),
+        );
+        index_cache.songs.push(Song {
+            id: 9,
+            title: "Legacy".to_owned(),
+            ..Song::default()
+        });
+
+        let mut incoming = export(
+            vec![
+                // Older than the local copy, so ignored
+                post(
+                    1,
+                    "Everlong (Acoustic) - Foo Fighters | #1",
+                    "2023-01-01 00:00:00",
+                ),
+                post(2, "Halo - Beyoncé | #2", "2024-06-01 00:00:00"),
+                post(3, "Stan - Eminem | #3", "2024-06-01 00:00:00"),
+            ],
+            &[3],
+        );
+        incoming.songs.push(Song {
+            id: 8,
+            title: "Other Legacy".to_owned(),
+            ..Song::default()
+        });
+
+        let summary = incoming.import(&mut index_cache, dir, true).unwrap();
+        index_cache.reparse();
+
+        assert_eq!(summary.posts_added, 1);
+        assert_eq!(summary.posts_replaced, 1);
+        let songs: Vec<_> = index_cache
+            .songs
+            .iter()
+            .map(|song| (song.id, song.title.as_str(), song.artist.as_str()))
+            .collect();
+        assert_eq!(
+            songs,
+            vec![
+                (1, "Everlong", "Foo Fighters"),
+                (2, "Halo", "Beyoncé"),
+                (3, "Stan", "Eminem"),
+                (9, "Legacy", ""),
+                (8, "Other Legacy", ""),
+            ]
+        );
+        assert_eq!(load_favorites(dir).unwrap(), Some(BTreeSet::from([1, 3])));
+
+        // Replacing drops what the export doesn't have
+        export(vec![], &[])
+            .import(&mut index_cache, dir, false)
+            .unwrap();
+        assert!(index_cache.posts.is_empty());
+        assert_eq!(load_favorites(dir).unwrap(), Some(BTreeSet::new()));
+    }
+
+    #[test]
+    fn test_read_checks_header() {
+        let dir = test_support::temp_dir();
+        let path = dir.path().join("export.json");
+
+        export(vec![post(1, "Everlong - Foo Fighters | #1", "")], &[1])
+            .write(&path)
+            .unwrap();
+        assert_eq!(CacheExport::read(&path).unwrap().posts.len(), 1);
+
+        fs::write(
+            &path,
+            json!({ "format": EXPORT_FORMAT, "version": EXPORT_VERSION + 1 }).to_string(),
+        )
+        .unwrap();
+        let error = CacheExport::read(&path).unwrap_err();
+        assert!(error.to_string().contains("newer version"));
+
+        fs::write(&path, "{}").unwrap();
+        assert!(CacheExport::read(&path).is_err());
+    }
+}
@@ -1,49 +1,510 @@
 use std::{
+    collections::HashSet,
+    fmt::Display,
     fs,
+    io::ErrorKind,
     path::{Path, PathBuf},
 };
 
-use anyhow::Result;
+use anyhow::{Context, Error, Result, bail};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::models::song::Song;
+use crate::{
+    api::post::RawPost, collation, config::StorageBackend, conversions::post::ParseContext,
+    corrections::Corrections, models::song::Song, storage,
+};
+
+mod migrations;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 
 const INDEX_CACHE_FILENAME: &str = "index.bin";
+/// Copy of the cache as it was before the last save
+const BACKUP_FILENAME: &str = "index.bin.bak";
+/// Start of every cache file written with a format header
+const MAGIC: &[u8; 4] = b"DSIX";
+/// Version of the current cache layout; see `migrations` when changing it
+const FORMAT_VERSION: u32 = 1;
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct IndexCache {
+    /// The `index.bin` file or `index.sqlite3` database the cache lives in
     #[serde(skip)]
     path: PathBuf,
+    #[serde(skip)]
+    backend: StorageBackend,
     pub songs: Vec<Song>,
     pub last_indexed: DateTime<Utc>,
+    /// Every fetched post as the API returned it, including ones that failed
+    /// to parse into a song
+    pub posts: Vec<RawPost>,
+    /// `ParseContext::fingerprint` of the settings the songs were parsed with
+    parse_fingerprint: u64,
+    #[serde(skip)]
+    parse_context: ParseContext,
+    #[serde(skip)]
+    needs_save: bool,
+}
+
+/// A cache file written by a newer version of the program, which this one
+/// must not fall back from or overwrite
+#[derive(Debug)]
+struct NewerFormatError(u32);
+
+impl Display for NewerFormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Cache format v{} was written by a newer version of drumscribe-index, which is needed to read it (this version reads up to v{FORMAT_VERSION})",
+            self.0
+        )
+    }
 }
 
+impl std::error::Error for NewerFormatError {}
+
 impl IndexCache {
-    pub fn load(data_dir: &Path) -> Self {
-        let index_cache_path = data_dir.join(INDEX_CACHE_FILENAME);
+    /// Loads the cache from the data directory using the given backend.
+    pub fn load(data_dir: &Path, backend: StorageBackend) -> Result<Self> {
+        match backend {
+            StorageBackend::File => Self::load_file(data_dir),
+            StorageBackend::Sqlite => Self::load_sqlite(data_dir),
+        }
+    }
 
-        if let Ok(bytes) = fs::read(&index_cache_path) {
-            IndexCache {
-                path: index_cache_path,
-                ..postcard::from_bytes(&bytes).unwrap_or(IndexCache::default())
-            }
-        } else {
-            IndexCache {
-                path: index_cache_path,
-                ..IndexCache::default()
+    /// Loads `index.bin`, upgrading it from an older format if needed. A
+    /// missing file is an empty cache. A file that can't be read falls back
+    /// to the backup kept by `save`, and is an error rather than being
+    /// silently replaced when that can't be read either.
+    fn load_file(data_dir: &Path) -> Result<Self> {
+        let path = data_dir.join(INDEX_CACHE_FILENAME);
+
+        let (version, cache, restored) = match Self::read(&path) {
+            Ok(Some((version, cache))) => (version, cache, false),
+            Ok(None) => (FORMAT_VERSION, IndexCache::default(), false),
+            Err(error) if error.is::<NewerFormatError>() => return Err(error),
+            Err(error) => {
+                let backup_path = data_dir.join(BACKUP_FILENAME);
+                let Ok(Some((version, cache))) = Self::read(&backup_path) else {
+                    return Err(
+                        error.context("Move or delete it to rebuild the index from the API")
+                    );
+                };
+
+                eprintln!(
+                    "Warning: {error:#}\nUsing the last good copy instead: {}",
+                    backup_path.display()
+                );
+                (version, cache, true)
             }
+        };
+
+        if version < FORMAT_VERSION {
+            eprintln!(
+                "Upgrading index cache from format v{version} to v{FORMAT_VERSION}: {}",
+                path.display()
+            );
+        }
+
+        Ok(IndexCache {
+            path,
+            needs_save: restored || version < FORMAT_VERSION,
+            ..cache
+        })
+    }
+
+    /// Loads `index.sqlite3`. Without a database yet, the index is carried
+    /// over from `index.bin`, which is left in place.
+    #[cfg(feature = "sqlite")]
+    fn load_sqlite(data_dir: &Path) -> Result<Self> {
+        let path = data_dir.join(sqlite::DATABASE_FILENAME);
+        if let Some(cache) = sqlite::load(&path)? {
+            return Ok(IndexCache {
+                path,
+                backend: StorageBackend::Sqlite,
+                ..cache
+            });
+        }
+
+        let cache = Self::load_file(data_dir)?;
+        if !cache.is_empty() {
+            eprintln!(
+                "Moving index cache from {} to {}",
+                cache.path.display(),
+                path.display()
+            );
         }
+
+        Ok(IndexCache {
+            path,
+            backend: StorageBackend::Sqlite,
+            needs_save: !cache.is_empty(),
+            ..cache
+        })
     }
 
+    #[cfg(not(feature = "sqlite"))]
+    fn load_sqlite(_data_dir: &Path) -> Result<Self> {
+        bail!(
+            "This build of drumscribe-index doesn't include SQLite storage; rebuild it with `--features sqlite` or use `--storage file`"
+        )
+    }
+
+    /// Like `load`, but a cache that can't be read is warned about and
+    /// treated as empty, for commands that rebuild it anyway. A cache written
+    /// by a newer version is still an error.
+    pub fn load_or_empty(data_dir: &Path, backend: StorageBackend) -> Result<Self> {
+        let error = match Self::load(data_dir, backend) {
+            Err(error) if !error.is::<NewerFormatError>() => error,
+            result => return result,
+        };
+        let path = match backend {
+            StorageBackend::File => data_dir.join(INDEX_CACHE_FILENAME),
+            #[cfg(feature = "sqlite")]
+            StorageBackend::Sqlite => data_dir.join(sqlite::DATABASE_FILENAME),
+            #[cfg(not(feature = "sqlite"))]
+            StorageBackend::Sqlite => return Err(error),
+        };
+
+        eprintln!("Warning: {error:#}\nStarting from an empty index cache");
+        Ok(IndexCache {
+            path,
+            backend,
+            ..IndexCache::default()
+        })
+    }
+
+    /// Reads and decodes a cache file, or `None` if there isn't one.
+    fn read(path: &Path) -> Result<Option<(u32, Self)>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+
+        Self::from_bytes(&bytes)
+            .map(Some)
+            .with_context(|| format!("Failed to read index cache: {}", path.display()))
+    }
+
+    /// Decodes a cache file, returning the format version it was written in.
+    fn from_bytes(bytes: &[u8]) -> Result<(u32, Self)> {
+        // Files written before the header existed
+        let Some(rest) = bytes.strip_prefix(MAGIC) else {
+            return Ok((0, migrations::migrate(0, bytes)?));
+        };
+
+        let (version, body): (u32, _) =
+            postcard::take_from_bytes(rest).context("Missing cache format version")?;
+        if version > FORMAT_VERSION {
+            bail!(NewerFormatError(version));
+        }
+
+        Ok((version, migrations::migrate(version, body)?))
+    }
+
+    /// Sets how posts are turned into songs from now on; songs already in the
+    /// cache are left alone until the next reparse.
+    pub fn with_parse_context(mut self, parse_context: ParseContext) -> Self {
+        self.parse_context = parse_context;
+        self
+    }
+
+    /// Writes the cache back to where it was loaded from.
     pub fn save(&self) -> Result<()> {
-        let bytes = postcard::to_allocvec(self)?;
-        fs::write(&self.path, &bytes)?;
+        match self.backend {
+            StorageBackend::File => self.save_file(),
+            #[cfg(feature = "sqlite")]
+            StorageBackend::Sqlite => sqlite::save(&self.path, self),
+            #[cfg(not(feature = "sqlite"))]
+            StorageBackend::Sqlite => unreachable!("SQLite caches can't be loaded in this build"),
+        }
+    }
+
+    /// Replaces the cache file atomically, keeping the file it replaces as
+    /// the backup `load` falls back to, as long as that one is readable.
+    fn save_file(&self) -> Result<()> {
+        if let Ok(bytes) = fs::read(&self.path)
+            && Self::from_bytes(&bytes).is_ok()
+        {
+            storage::write_atomic(&self.path.with_file_name(BACKUP_FILENAME), &bytes)?;
+        }
 
-        Ok(())
+        storage::write_atomic(&self.path, &self.to_bytes()?)
+    }
+
+    /// Whether the cache was upgraded from an older format or restored from
+    /// the backup, and should be saved.
+    pub fn needs_save(&self) -> bool {
+        self.needs_save
+    }
+
+    /// Encodes the cache behind the `MAGIC` and `FORMAT_VERSION` header.
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(postcard::to_extend(
+            &(FORMAT_VERSION, self),
+            MAGIC.to_vec(),
+        )?)
+    }
+
+    pub fn corrections(&self) -> &Corrections {
+        self.parse_context.corrections()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.songs.is_empty()
+        self.songs.is_empty() && self.posts.is_empty()
+    }
+
+    /// Whether the corrections or difficulty settings changed since the songs
+    /// were parsed.
+    pub fn is_stale(&self) -> bool {
+        !self.is_empty() && self.parse_fingerprint != self.parse_context.fingerprint()
+    }
+
+    /// Replaces the cached posts and rebuilds the songs from them.
+    pub fn set_posts(&mut self, posts: Vec<RawPost>) {
+        self.posts = posts;
+        self.parse_fingerprint = self.parse_context.fingerprint();
+        self.songs = self
+            .posts
+            .iter()
+            .flat_map(|post| self.parse_context.parse_raw(post))
+            .collect();
+    }
+
+    /// Adds newly fetched posts along with their songs.
+    pub fn extend_posts(&mut self, posts: impl IntoIterator<Item = RawPost>) {
+        for post in posts {
+            self.songs.extend(self.parse_context.parse_raw(&post));
+            self.posts.push(post);
+        }
+    }
+
+    /// IDs of every indexed post, whether or not it parsed into a song.
+    pub fn known_post_ids(&self) -> HashSet<usize> {
+        self.posts
+            .iter()
+            .map(|post| post.id)
+            .chain(self.songs.iter().map(|s| s.id))
+            .collect()
+    }
+
+    /// IDs of the songs whose title, artist or featured artists have a word
+    /// starting with each word of `query`, ignoring case and accents. With
+    /// `StorageBackend::Sqlite` this asks the database's full-text index,
+    /// which reflects the last save.
+    pub fn search(&self, query: &str) -> Result<HashSet<usize>> {
+        #[cfg(feature = "sqlite")]
+        if self.backend == StorageBackend::Sqlite {
+            return sqlite::search(&self.path, query);
+        }
+
+        let query_words = collation::words(query);
+        if query_words.is_empty() {
+            bail!("The search query is empty");
+        }
+
+        Ok(self
+            .songs
+            .iter()
+            .filter(|song| {
+                let song_words: Vec<_> = std::iter::once(&song.title)
+                    .chain(std::iter::once(&song.artist))
+                    .chain(&song.featured_artists)
+                    .flat_map(|text| collation::words(text))
+                    .collect();
+
+                query_words.iter().all(|query_word| {
+                    song_words
+                        .iter()
+                        .any(|song_word| song_word.starts_with(query_word.as_str()))
+                })
+            })
+            .map(|song| song.id)
+            .collect())
+    }
+
+    /// Stored posts that can't be turned into a song, with the reason.
+    pub fn parse_failures(&self) -> impl Iterator<Item = (&RawPost, Error)> {
+        self.posts.iter().filter_map(|post| {
+            self.parse_context
+                .parse_raw(post)
+                .err()
+                .map(|error| (post, error))
+        })
+    }
+
+    /// Rebuilds every song from its stored post so parser fixes and
+    /// corrections apply to the whole catalog. Songs cached before raw posts
+    /// were stored are kept as they are, since they were corrected when
+    /// cached and applying a rule like `X` → `X (Live)` again would repeat
+    /// it; returns how many of those there were.
+    pub fn reparse(&mut self) -> usize {
+        let post_ids: HashSet<_> = self.posts.iter().map(|post| post.id).collect();
+        let legacy_songs: Vec<_> = self
+            .songs
+            .drain(..)
+            .filter(|song| !post_ids.contains(&song.id))
+            .collect();
+        let legacy_count = legacy_songs.len();
+
+        self.songs = self
+            .posts
+            .iter()
+            .flat_map(|post| self.parse_context.parse_raw(post))
+            .collect();
+        self.songs.extend(legacy_songs);
+        self.parse_fingerprint = self.parse_context.fingerprint();
+
+        legacy_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{self, PostJson};
+
+    use super::*;
+
+    #[test]
+    fn test_reparse_rebuilds_from_posts_and_keeps_legacy_songs() {
+        let mut index_cache = IndexCache::default();
+        index_cache.set_posts(vec![
+            PostJson::new(1, "Everlong - Foo Fighters | #42").raw(),
+        ]);
+
+        // Simulate a stale parse and a song cached before posts were stored
+        index_cache.songs[0].title = "Everlong - Foo Fighters".to_owned();
+        index_cache.songs.push(Song {
+            id: 2,
+            title: "Legacy".to_owned(),
+            ..Song::default()
+        });
+
+        assert_eq!(index_cache.reparse(), 1);
+        let titles = |index_cache: &IndexCache| {
+            index_cache
+                .songs
+                .iter()
+                .map(|s| s.title.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles(&index_cache), vec!["Everlong", "Legacy"]);
+
+        // A rule that would repeat itself only applies to songs with a post
+        let corrections = Corrections::parse(
+            r#"
+            [[rules]]
+            field = "title"
+            pattern = "^(Everlong|Legacy)"
+            replacement = "$1 (Live)"
+            "#,
+        )
+        .unwrap();
+        let mut index_cache = index_cache.with_parse_context(ParseContext::new(
+            &crate::config::Config::default(),
+            corrections,
+        ));
+        index_cache.reparse();
+        index_cache.reparse();
+        assert_eq!(titles(&index_cache), vec!["Everlong (Live)", "Legacy"]);
+    }
+
+    #[test]
+    fn test_format_header() {
+        let mut index_cache = IndexCache::default();
+        index_cache.songs.push(Song {
+            id: 1,
+            title: "Everlong".to_owned(),
+            ..Song::default()
+        });
+
+        let bytes = index_cache.to_bytes().unwrap();
+        assert!(bytes.starts_with(MAGIC));
+        let (version, loaded) = IndexCache::from_bytes(&bytes).unwrap();
+        assert_eq!(version, FORMAT_VERSION);
+        assert_eq!(loaded.songs, index_cache.songs);
+
+        let mut newer = MAGIC.to_vec();
+        newer.push(u8::try_from(FORMAT_VERSION + 1).unwrap());
+        let error = IndexCache::from_bytes(&newer).unwrap_err();
+        assert!(error.to_string().contains("newer version"));
+
+        assert!(IndexCache::from_bytes(b"\xff\xff\xff").is_err());
+    }
+
+    #[test]
+    fn test_load_falls_back_to_backup() {
+        let temp_dir = test_support::temp_dir();
+        let dir = temp_dir.path();
+
+        let mut index_cache = IndexCache::load(dir, StorageBackend::File).unwrap();
+        index_cache.songs.push(Song {
+            id: 1,
+            ..Song::default()
+        });
+        index_cache.save().unwrap();
+        index_cache.songs.push(Song {
+            id: 2,
+            ..Song::default()
+        });
+        index_cache.save().unwrap();
+        assert_eq!(
+            IndexCache::load(dir, StorageBackend::File)
+                .unwrap()
+                .songs
+                .len(),
+            2
+        );
+
+        // A damaged cache falls back to the copy from before the last save
+        fs::write(dir.join(INDEX_CACHE_FILENAME), b"\xff\xff").unwrap();
+        let restored = IndexCache::load(dir, StorageBackend::File).unwrap();
+        assert_eq!(restored.songs.len(), 1);
+        assert!(restored.needs_save());
+
+        fs::write(dir.join(BACKUP_FILENAME), b"\xff\xff").unwrap();
+        assert!(IndexCache::load(dir, StorageBackend::File).is_err());
+
+        // Unless the cache is about to be rebuilt anyway
+        let empty = IndexCache::load_or_empty(dir, StorageBackend::File).unwrap();
+        assert!(empty.is_empty());
+        empty.save().unwrap();
+        assert!(IndexCache::load(dir, StorageBackend::File).is_ok());
+
+        // A cache from a newer version is never replaced
+        let mut newer = MAGIC.to_vec();
+        newer.push(u8::try_from(FORMAT_VERSION + 1).unwrap());
+        fs::write(dir.join(INDEX_CACHE_FILENAME), newer).unwrap();
+        assert!(IndexCache::load_or_empty(dir, StorageBackend::File).is_err());
+    }
+
+    #[test]
+    fn test_search() {
+        let index_cache = IndexCache {
+            songs: vec![
+                Song {
+                    id: 1,
+                    artist: "Beyoncé".to_owned(),
+                    featured_artists: vec!["Jay-Z".to_owned()],
+                    title: "Crazy in Love".to_owned(),
+                    ..Song::default()
+                },
+                Song {
+                    id: 2,
+                    artist: "Foo Fighters".to_owned(),
+                    title: "Everlong".to_owned(),
+                    ..Song::default()
+                },
+            ],
+            ..IndexCache::default()
+        };
+
+        assert_eq!(index_cache.search("beyonce").unwrap(), HashSet::from([1]));
+        assert_eq!(index_cache.search("CRAZY jay").unwrap(), HashSet::from([1]));
+        assert_eq!(index_cache.search("foo ever").unwrap(), HashSet::from([2]));
+        assert!(index_cache.search("long").unwrap().is_empty());
+        assert!(index_cache.search("  ").is_err());
     }
 }
@@ -0,0 +1,159 @@
+use anyhow::{Context, Result};
+
+use super::IndexCache;
+
+/// Upgrades the body of a cache file from one format version to the next.
+type Migration = fn(&[u8]) -> Result<Vec<u8>>;
+
+/// `MIGRATIONS[n]` turns a version `n` body into a version `n + 1` body.
+///
+/// When the layout of `IndexCache` or anything inside it changes, freeze the
+/// outgoing definitions in a new `vN` module, bump `FORMAT_VERSION` and add
+/// the step from `vN` here.
+const MIGRATIONS: [Migration; super::FORMAT_VERSION as usize] = [v0::migrate];
+
+/// Decodes a cache body written in format `version`, upgrading it through
+/// every later version first.
+pub fn migrate(mut version: u32, bytes: &[u8]) -> Result<IndexCache> {
+    let mut bytes = bytes.to_vec();
+
+    while version < super::FORMAT_VERSION {
+        bytes = MIGRATIONS[version as usize](&bytes)
+            .with_context(|| format!("Failed to upgrade from cache format v{version}"))?;
+        version += 1;
+    }
+
+    Ok(postcard::from_bytes(&bytes)?)
+}
+
+/// The layout before the cache had a header: songs only, without the posts
+/// they came from, and the sequence number as a plain string.
+mod v0 {
+    use chrono::{DateTime, Utc};
+    use serde::Deserialize;
+
+    use super::Result;
+    use crate::{index_cache, models::song};
+
+    #[derive(Deserialize)]
+    #[cfg_attr(test, derive(serde::Serialize))]
+    pub struct IndexCache {
+        pub songs: Vec<Song>,
+        pub last_indexed: DateTime<Utc>,
+    }
+
+    #[derive(Deserialize)]
+    #[cfg_attr(test, derive(serde::Serialize))]
+    pub struct Song {
+        pub id: usize,
+        pub artist: String,
+        pub title: String,
+        pub difficulty: Difficulty,
+        pub link: String,
+        pub sequence_number: String,
+    }
+
+    #[derive(Deserialize)]
+    #[cfg_attr(test, derive(serde::Serialize))]
+    pub enum Difficulty {
+        Beginner,
+        Intermediate,
+        Advanced,
+        Expert,
+        Master,
+        Unrated,
+    }
+
+    impl From<Difficulty> for song::Difficulty {
+        fn from(difficulty: Difficulty) -> Self {
+            match difficulty {
+                Difficulty::Beginner => song::Difficulty::Beginner,
+                Difficulty::Intermediate => song::Difficulty::Intermediate,
+                Difficulty::Advanced => song::Difficulty::Advanced,
+                Difficulty::Expert => song::Difficulty::Expert,
+                Difficulty::Master => song::Difficulty::Master,
+                Difficulty::Unrated => song::Difficulty::Unrated,
+            }
+        }
+    }
+
+    impl From<Song> for song::Song {
+        fn from(song: Song) -> Self {
+            let sequence_number = song.sequence_number.parse().ok();
+            // The old parser only understood the standard layout, so whatever
+            // it found all of came from a standard heading
+            let parse_confidence =
+                if song.artist.is_empty() || song.title.is_empty() || sequence_number.is_none() {
+                    song::Confidence::Low
+                } else {
+                    song::Confidence::High
+                };
+
+            song::Song {
+                id: song.id,
+                artist: song.artist,
+                title: song.title,
+                difficulty: song.difficulty.into(),
+                link: song.link,
+                sequence_number,
+                parse_confidence,
+                ..song::Song::default()
+            }
+        }
+    }
+
+    pub fn migrate(bytes: &[u8]) -> Result<Vec<u8>> {
+        let cache: IndexCache = postcard::from_bytes(bytes)?;
+
+        let cache = index_cache::IndexCache {
+            songs: cache.songs.into_iter().map(song::Song::from).collect(),
+            last_indexed: cache.last_indexed,
+            ..index_cache::IndexCache::default()
+        };
+
+        Ok(postcard::to_allocvec(&cache)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::models::song::{Confidence, Difficulty};
+
+    #[test]
+    fn test_migrate_v0() {
+        let cache = v0::IndexCache {
+            songs: vec![
+                v0::Song {
+                    id: 1,
+                    artist: "Foo Fighters".to_owned(),
+                    title: "Everlong".to_owned(),
+                    difficulty: v0::Difficulty::Expert,
+                    link: "https://example.com/1".to_owned(),
+                    sequence_number: "42".to_owned(),
+                },
+                v0::Song {
+                    id: 2,
+                    artist: String::new(),
+                    title: "Mystery Song".to_owned(),
+                    difficulty: v0::Difficulty::Unrated,
+                    link: "https://example.com/2".to_owned(),
+                    sequence_number: String::new(),
+                },
+            ],
+            last_indexed: Utc::now(),
+        };
+
+        let migrated = migrate(0, &postcard::to_allocvec(&cache).unwrap()).unwrap();
+
+        assert_eq!(migrated.last_indexed, cache.last_indexed);
+        assert_eq!(migrated.songs[0].difficulty, Difficulty::Expert);
+        assert_eq!(migrated.songs[0].sequence_label(), "#42");
+        assert_eq!(migrated.songs[0].parse_confidence, Confidence::High);
+        assert_eq!(migrated.songs[1].sequence_number, None);
+        assert_eq!(migrated.songs[1].parse_confidence, Confidence::Low);
+        assert!(migrated.posts.is_empty());
+    }
+}
@@ -0,0 +1,283 @@
+use std::{collections::HashSet, path::Path};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params};
+
+use super::IndexCache;
+use crate::{api::post::RawPost, models::song::Song};
+
+pub const DATABASE_FILENAME: &str = "index.sqlite3";
+/// `PRAGMA user_version` of the current schema
+const SCHEMA_VERSION: u32 = 1;
+
+/// Songs keep their full JSON in `data`; the other columns are copies for
+/// querying. Rows are kept in cache order, so `rowid` is the position.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS posts (
+    id INTEGER NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS posts_id ON posts (id);
+
+CREATE TABLE IF NOT EXISTS songs (
+    id INTEGER NOT NULL,
+    artist TEXT NOT NULL,
+    featured_artists TEXT NOT NULL,
+    title TEXT NOT NULL,
+    difficulty TEXT NOT NULL,
+    sequence_number INTEGER,
+    sequence_suffix TEXT,
+    published_at TEXT,
+    updated_at TEXT,
+    link TEXT NOT NULL,
+    members_only INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS songs_id ON songs (id);
+CREATE INDEX IF NOT EXISTS songs_difficulty ON songs (difficulty);
+CREATE INDEX IF NOT EXISTS songs_sequence ON songs (sequence_number, sequence_suffix);
+CREATE INDEX IF NOT EXISTS songs_published_at ON songs (published_at);
+CREATE INDEX IF NOT EXISTS songs_updated_at ON songs (updated_at);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS songs_fts USING fts5 (
+    title,
+    artist,
+    featured_artists,
+    content = 'songs',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+";
+
+/// Opens the database for reading, without creating or changing anything,
+/// since readers don't hold the data lock. `None` if there's no database or
+/// its schema was never created.
+fn open_read_only(path: &Path) -> Result<Option<Connection>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    match schema_version(&connection)? {
+        0 => Ok(None),
+        _ => Ok(Some(connection)),
+    }
+}
+
+/// Opens the database for writing, creating the schema if needed. Only for
+/// runs holding the data lock.
+fn open_read_write(path: &Path) -> Result<Connection> {
+    let connection = Connection::open(path)?;
+
+    if schema_version(&connection)? < SCHEMA_VERSION {
+        connection.execute_batch(SCHEMA)?;
+        connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    }
+
+    Ok(connection)
+}
+
+fn schema_version(connection: &Connection) -> Result<u32> {
+    let version: u32 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        bail!(
+            "Database schema v{version} was written by a newer version of drumscribe-index (this version reads up to v{SCHEMA_VERSION})"
+        );
+    }
+
+    Ok(version)
+}
+
+fn read_meta(connection: &Connection, key: &str) -> Result<Option<String>> {
+    Ok(connection
+        .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+            row.get(0)
+        })
+        .optional()?)
+}
+
+/// Reads the cache from the database, or `None` if there isn't one.
+pub fn load(path: &Path) -> Result<Option<IndexCache>> {
+    let read = || -> Result<Option<IndexCache>> {
+        let Some(connection) = open_read_only(path)? else {
+            return Ok(None);
+        };
+
+        let songs = connection
+            .prepare("SELECT data FROM songs ORDER BY rowid")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|data| Ok(serde_json::from_str::<Song>(&data?)?))
+            .collect::<Result<_>>()?;
+        let posts = connection
+            .prepare("SELECT id, json FROM posts ORDER BY rowid")?
+            .query_map([], |row| {
+                Ok(RawPost {
+                    id: row.get(0)?,
+                    json: row.get(1)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(Some(IndexCache {
+            songs,
+            posts,
+            last_indexed: read_meta(&connection, "last_indexed")?
+                .map(|value| DateTime::parse_from_rfc3339(&value))
+                .transpose()?
+                .map(|date| date.with_timezone(&Utc))
+                .unwrap_or_default(),
+            parse_fingerprint: read_meta(&connection, "parse_fingerprint")?
+                .map(|value| value.parse())
+                .transpose()?
+                .unwrap_or_default(),
+            ..IndexCache::default()
+        }))
+    };
+
+    read().with_context(|| format!("Failed to read index database: {}", path.display()))
+}
+
+/// Replaces everything in the database with `cache` in one transaction.
+pub fn save(path: &Path, cache: &IndexCache) -> Result<()> {
+    let write = || -> Result<()> {
+        let mut connection = open_read_write(path)?;
+        let transaction = connection.transaction()?;
+
+        transaction.execute_batch("DELETE FROM songs; DELETE FROM posts;")?;
+        {
+            let mut insert_song = transaction.prepare(
+                "INSERT INTO songs (id, artist, featured_artists, title, difficulty,
+                    sequence_number, sequence_suffix, published_at, updated_at, link,
+                    members_only, data)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            )?;
+            for song in &cache.songs {
+                let difficulty = serde_json::to_value(&song.difficulty)?;
+                insert_song.execute(params![
+                    song.id,
+                    song.artist,
+                    song.featured_artists.join(", "),
+                    song.title,
+                    difficulty.as_str(),
+                    song.sequence_number.as_ref().map(|number| number.number),
+                    song.sequence_number.as_ref().map(|number| &number.suffix),
+                    song.published_at.map(|date| date.to_rfc3339()),
+                    song.updated_at.map(|date| date.to_rfc3339()),
+                    song.link,
+                    song.members_only,
+                    serde_json::to_string(song)?,
+                ])?;
+            }
+
+            let mut insert_post =
+                transaction.prepare("INSERT INTO posts (id, json) VALUES (?1, ?2)")?;
+            for post in &cache.posts {
+                insert_post.execute(params![post.id, post.json])?;
+            }
+        }
+
+        transaction.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('last_indexed', ?1), ('parse_fingerprint', ?2)",
+            params![
+                cache.last_indexed.to_rfc3339(),
+                cache.parse_fingerprint.to_string()
+            ],
+        )?;
+        transaction.execute("INSERT INTO songs_fts (songs_fts) VALUES ('rebuild')", [])?;
+
+        Ok(transaction.commit()?)
+    };
+
+    write().with_context(|| format!("Failed to write index database: {}", path.display()))
+}
+
+/// IDs of the songs matching every word of `query` through the full-text
+/// index.
+pub fn search(path: &Path, query: &str) -> Result<HashSet<usize>> {
+    // Quote every word so FTS syntax in the query is taken literally, and
+    // match it as a prefix
+    let fts_query = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if fts_query.is_empty() {
+        bail!("The search query is empty");
+    }
+
+    // Nothing has been saved yet
+    let Some(connection) = open_read_only(path)? else {
+        return Ok(HashSet::new());
+    };
+    let ids = connection
+        .prepare(
+            "SELECT songs.id FROM songs_fts
+            JOIN songs ON songs.rowid = songs_fts.rowid
+            WHERE songs_fts MATCH ?1",
+        )?
+        .query_map([fts_query], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::test_support;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_search() {
+        let dir = test_support::temp_dir();
+        let path = dir.path().join(DATABASE_FILENAME);
+        assert!(load(&path).unwrap().is_none());
+        assert!(search(&path, "foo").unwrap().is_empty());
+
+        // Reading never creates the schema
+        fs::write(&path, b"").unwrap();
+        assert!(load(&path).unwrap().is_none());
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+
+        let cache = IndexCache {
+            songs: vec![
+                Song {
+                    id: 1,
+                    artist: "Beyoncé".to_owned(),
+                    title: "Halo".to_owned(),
+                    sequence_number: Some(42.into()),
+                    ..Song::default()
+                },
+                Song {
+                    id: 2,
+                    artist: "Foo Fighters".to_owned(),
+                    title: "Everlong".to_owned(),
+                    ..Song::default()
+                },
+            ],
+            last_indexed: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            parse_fingerprint: u64::MAX,
+            ..IndexCache::default()
+        };
+        save(&path, &cache).unwrap();
+        save(&path, &cache).unwrap();
+
+        let loaded = load(&path).unwrap().unwrap();
+        assert_eq!(loaded.songs, cache.songs);
+        assert_eq!(loaded.last_indexed, cache.last_indexed);
+        assert_eq!(loaded.parse_fingerprint, u64::MAX);
+
+        assert_eq!(search(&path, "beyonce").unwrap(), HashSet::from([1]));
+        assert_eq!(search(&path, "foo ever").unwrap(), HashSet::from([2]));
+        // FTS syntax is taken as plain text
+        assert_eq!(search(&path, "\"foo OR").unwrap(), HashSet::new());
+        assert_eq!(search(&path, "\"foo").unwrap(), HashSet::from([2]));
+    }
+}
@@ -1,40 +1,56 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs,
-    io::{self, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
 use chrono::Utc;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use directories::ProjectDirs;
 
 use crate::{
-    api::{
-        coffee_api::{CoffeeApi, PageResponse},
-        post::Post,
-    },
+    api::{coffee_api::CoffeeApi, response_cache::ResponseCache},
+    collation::Collation,
+    config::{ArtistOrder, Config, StorageBackend},
+    conversions::post::ParseContext,
+    corrections::Corrections,
+    doctor::DoctorReport,
+    export::CacheExport,
     index_cache::IndexCache,
     models::song::{Song, SongGroup},
     output::{
         html::HtmlFormatter, json::JsonFormatter, markdown::MarkdownFormatter, pdf::PdfFormatter,
         xlsx::XlsxFormatter,
     },
+    storage::DataLock,
+    update::ChangeSummary,
 };
 
 mod api;
+mod artist_merge;
+mod collation;
+mod config;
 mod conversions;
 mod corrections;
+mod doctor;
+mod export;
 mod index_cache;
 mod models;
 mod output;
+mod storage;
+#[cfg(test)]
+mod test_support;
+mod update;
 
 #[derive(Parser)]
 #[command(name = "drumscribe-index")]
 #[command(about = "DrumScribe song index generator")]
 #[allow(clippy::struct_excessive_bools)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Output in JSON format (default)
     #[arg(long, group = "format")]
     json: bool,
@@ -59,38 +75,263 @@ struct Cli {
     #[arg(long, value_name = "PATH")]
     output: Option<String>,
 
+    /// Only include songs by this artist, whether credited as the primary or a featured artist
+    #[arg(long, value_name = "NAME")]
+    artist: Option<String>,
+
+    /// Only include songs with this tag name or ID; repeat to require several tags
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+
+    /// Only include songs with a word in the title or artists starting with each word of the query
+    #[arg(long, value_name = "QUERY")]
+    search: Option<String>,
+
     /// Update list of indexed songs
     #[arg(long)]
     update: bool,
 
+    /// Re-fetch the whole catalog, picking up edited and deleted posts
+    #[arg(long, conflicts_with = "check")]
+    resync: bool,
+
+    /// Rebuild every cached song from its stored post, without fetching
+    #[arg(long, conflicts_with_all = ["check", "resync"])]
+    reparse: bool,
+
     /// Check whether new songs are available without updating the cache
     #[arg(long)]
     check: bool,
+
+    /// Save every raw API page fetched during this run to the given directory
+    #[arg(long, value_name = "DIR", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Read API pages from a directory made with --record instead of the network
+    #[arg(long, value_name = "DIR")]
+    replay: Option<PathBuf>,
+
+    /// Base URL of the Buy Me a Coffee API (overrides config.toml)
+    #[arg(long, env = "DRUMSCRIBE_API_URL", value_name = "URL")]
+    api_url: Option<String>,
+
+    /// Creator whose posts are indexed (overrides config.toml)
+    #[arg(long, env = "DRUMSCRIBE_CREATOR", value_name = "NAME")]
+    creator: Option<String>,
+
+    /// Category ID of transcription posts (overrides config.toml)
+    #[arg(long, env = "DRUMSCRIBE_CATEGORY_ID", value_name = "ID")]
+    category_id: Option<usize>,
+
+    /// Request timeout in seconds (overrides config.toml)
+    #[arg(long, env = "DRUMSCRIBE_TIMEOUT", value_name = "SECONDS")]
+    timeout: Option<u64>,
+
+    /// Connection timeout in seconds (overrides config.toml)
+    #[arg(long, env = "DRUMSCRIBE_CONNECT_TIMEOUT", value_name = "SECONDS")]
+    connect_timeout: Option<u64>,
+
+    /// Proxy URL for all requests (overrides config.toml)
+    #[arg(long, env = "DRUMSCRIBE_PROXY", value_name = "URL")]
+    proxy: Option<String>,
+
+    /// User-Agent header sent with requests (overrides config.toml)
+    #[arg(long, env = "DRUMSCRIBE_USER_AGENT", value_name = "STRING")]
+    user_agent: Option<String>,
+
+    /// PEM file of additional trusted CA certificates (overrides config.toml)
+    #[arg(long, env = "DRUMSCRIBE_CA_BUNDLE", value_name = "PATH")]
+    ca_bundle: Option<PathBuf>,
+
+    /// Attempts per page before giving up on transient errors (overrides config.toml)
+    #[arg(long, env = "DRUMSCRIBE_MAX_ATTEMPTS", value_name = "COUNT")]
+    max_attempts: Option<u32>,
+
+    /// Posts requested per page (overrides config.toml)
+    #[arg(long, env = "DRUMSCRIBE_PAGE_SIZE", value_name = "COUNT")]
+    page_size: Option<usize>,
+
+    /// Pages fetched in parallel while building the full index (overrides config.toml)
+    #[arg(long, env = "DRUMSCRIBE_CONCURRENCY", value_name = "COUNT")]
+    concurrency: Option<usize>,
+
+    /// How artists are ordered in grouped output (overrides config.toml)
+    #[arg(long, env = "DRUMSCRIBE_ARTIST_ORDER", value_name = "ORDER")]
+    artist_order: Option<ArtistOrder>,
+
+    /// Language whose alphabetical order is used for sorting, e.g. sv-SE (overrides config.toml)
+    #[arg(long, env = "DRUMSCRIBE_LOCALE", value_name = "LOCALE")]
+    locale: Option<String>,
+
+    /// Where the index is stored (overrides config.toml)
+    #[arg(long, env = "DRUMSCRIBE_STORAGE", value_name = "BACKEND")]
+    storage: Option<StorageBackend>,
+
+    /// Directory for the cache, config.toml, corrections.toml and everything else kept between runs
+    #[arg(long, env = "DRUMSCRIBE_DATA_DIR", value_name = "DIR", global = true)]
+    data_dir: Option<PathBuf>,
 }
 
-fn create_data_dir() -> Result<PathBuf> {
-    let project_dirs = ProjectDirs::from("com", "xapphire13", env!("CARGO_PKG_NAME"))
-        .ok_or(anyhow!("Can't load project dirs"))?;
+#[derive(Subcommand)]
+enum Command {
+    /// Report posts and songs that didn't make it cleanly into the index
+    Doctor {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Exit with a non-zero status if any problem is found
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Suggest merges for artist names that differ only by case, punctuation,
+    /// articles or a typo
+    MergeArtists {
+        /// Write the merges to corrections.toml and apply them to the cache
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Copy the index and favorites to or from a portable file
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
 
-    let data_dir = project_dirs.data_dir();
-    fs::create_dir_all(data_dir)?;
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Write the index, favorites and corrections to a JSON file
+    Export { file: PathBuf },
+    /// Replace the index, favorites and corrections with those from an exported file
+    Import {
+        file: PathBuf,
+
+        /// Combine with the current index by post ID instead of replacing it,
+        /// keeping the more recently updated copy of posts in both
+        #[arg(long)]
+        merge: bool,
+    },
+}
+
+/// Creates the data directory if needed: the one given with `--data-dir`,
+/// or the platform's application data directory.
+fn create_data_dir(cli: &Cli) -> Result<PathBuf> {
+    let data_dir = if let Some(data_dir) = &cli.data_dir {
+        data_dir.clone()
+    } else {
+        ProjectDirs::from("com", "xapphire13", env!("CARGO_PKG_NAME"))
+            .ok_or(anyhow!("Can't load project dirs"))?
+            .data_dir()
+            .to_path_buf()
+    };
+
+    fs::create_dir_all(&data_dir)
+        .with_context(|| format!("Failed to create data directory: {}", data_dir.display()))?;
+
+    Ok(data_dir)
+}
+
+fn load_config(data_dir: &Path, cli: &Cli) -> Result<Config> {
+    let mut config = Config::load(data_dir)?;
+
+    if let Some(api_url) = &cli.api_url {
+        config.api.base_url.clone_from(api_url);
+    }
+    if let Some(creator) = &cli.creator {
+        config.api.creator.clone_from(creator);
+    }
+    if let Some(category_id) = cli.category_id {
+        config.api.category_id = category_id;
+    }
+    if let Some(timeout) = cli.timeout {
+        config.http.timeout_secs = timeout;
+    }
+    if let Some(connect_timeout) = cli.connect_timeout {
+        config.http.connect_timeout_secs = connect_timeout;
+    }
+    if let Some(proxy) = &cli.proxy {
+        config.http.proxy = Some(proxy.clone());
+    }
+    if let Some(user_agent) = &cli.user_agent {
+        config.http.user_agent.clone_from(user_agent);
+    }
+    if let Some(ca_bundle) = &cli.ca_bundle {
+        config.http.ca_bundle = Some(ca_bundle.clone());
+    }
+    if let Some(max_attempts) = cli.max_attempts {
+        config.retry.max_attempts = max_attempts;
+    }
+    if let Some(page_size) = cli.page_size {
+        config.fetch.page_size = page_size;
+    }
+    if let Some(concurrency) = cli.concurrency {
+        config.fetch.concurrency = concurrency;
+    }
+    if let Some(artist_order) = cli.artist_order {
+        config.output.artist_order = artist_order;
+    }
+    if let Some(locale) = &cli.locale {
+        config.output.locale = Some(locale.clone());
+    }
+    if let Some(storage) = cli.storage {
+        config.storage.backend = storage;
+    }
+
+    Ok(config)
+}
 
-    Ok(data_dir.to_path_buf())
+/// Applies the `--artist`, `--tag` and `--search` filters.
+fn filter_songs(index_cache: &IndexCache, cli: &Cli) -> Result<Vec<Song>> {
+    let search_ids = cli
+        .search
+        .as_ref()
+        .map(|query| index_cache.search(query))
+        .transpose()?;
+
+    Ok(index_cache
+        .songs
+        .iter()
+        .filter(|song| search_ids.as_ref().is_none_or(|ids| ids.contains(&song.id)))
+        .filter(|song| {
+            cli.artist
+                .as_ref()
+                .is_none_or(|artist| song.has_artist(artist))
+        })
+        .filter(|song| {
+            cli.tags
+                .iter()
+                .all(|filter| song.tags.iter().any(|tag| tag.matches(filter)))
+        })
+        .cloned()
+        .collect())
 }
 
-fn group_songs(songs: &[Song]) -> Vec<SongGroup> {
+fn group_songs(
+    songs: &[Song],
+    corrections: &Corrections,
+    order: ArtistOrder,
+    collator: &Collation,
+) -> Vec<SongGroup> {
+    fn name(group: &SongGroup, order: ArtistOrder) -> &str {
+        match order {
+            ArtistOrder::SortName => &group.sort_name,
+            ArtistOrder::Name => &group.artist,
+        }
+    }
+
     let mut groups = HashMap::new();
 
     for song in songs {
-        if song.sequence_number.is_empty() {
+        if song.sequence_number.is_none() {
             continue;
         }
 
-        let key = song.artist.to_lowercase();
+        let key = collation::fold(&song.artist);
         groups
             .entry(key)
-            .or_insert(SongGroup {
+            .or_insert_with(|| SongGroup {
                 artist: song.artist.clone(),
+                sort_name: corrections.sort_name(&song.artist),
                 songs: vec![],
             })
             .songs
@@ -98,96 +339,281 @@ fn group_songs(songs: &[Song]) -> Vec<SongGroup> {
     }
 
     let mut result: Vec<_> = groups.into_values().collect();
-    result.sort_unstable_by_key(|group| group.artist.to_lowercase());
+    result.sort_by(|a, b| {
+        collator
+            .compare(name(a, order), name(b, order))
+            .then_with(|| collator.compare(&a.artist, &b.artist))
+    });
 
     for group in &mut result {
         group
             .songs
-            .sort_unstable_by_key(|song| song.title.to_lowercase());
+            .sort_by(|a, b| collator.compare(&a.title, &b.title));
 
-        group.songs.dedup_by_key(|song| song.title.to_lowercase());
+        // Titles that fold the same needn't sort next to each other
+        let mut titles = HashSet::new();
+        group
+            .songs
+            .retain(|song| titles.insert(collation::fold(&song.title)));
     }
 
     result
 }
 
+/// Whether the run writes the cache whatever it holds, so it must hold the
+/// data lock from the start.
+fn always_writes(cli: &Cli) -> bool {
+    cli.update
+        || cli.resync
+        || cli.reparse
+        || matches!(cli.command, Some(Command::MergeArtists { apply: true }))
+}
+
+/// Loads the cache, reparsing it when the corrections or difficulty settings
+/// changed and saving it when that or loading it changed anything. Runs that
+/// write take the data lock, which is returned to be held until they finish;
+/// read-only runs only take it when loading turns out to need a save.
+fn load_index_cache(
+    data_dir: &Path,
+    config: &Config,
+    cli: &Cli,
+) -> Result<(IndexCache, Option<DataLock>)> {
+    let load = || -> Result<IndexCache> {
+        let corrections = Corrections::load(data_dir)?;
+        Ok(IndexCache::load(data_dir, config.storage.backend)?
+            .with_parse_context(ParseContext::new(config, corrections)))
+    };
+    // --check never writes the cache, so it doesn't wait for a running update
+    let needs_save = |index_cache: &IndexCache| {
+        !cli.check && (index_cache.needs_save() || index_cache.is_stale() && !cli.reparse)
+    };
+
+    let mut lock = always_writes(cli)
+        .then(|| DataLock::acquire(data_dir))
+        .transpose()?;
+    let mut index_cache = load()?;
+    // An empty cache gets fully indexed, unless a subcommand runs instead
+    let will_index = !cli.check && cli.command.is_none() && index_cache.is_empty();
+    if lock.is_none() && (needs_save(&index_cache) || will_index) {
+        lock = Some(DataLock::acquire(data_dir)?);
+        // Another run may have updated the cache before the lock was free
+        index_cache = load()?;
+    }
+
+    let needs_save = needs_save(&index_cache);
+    if index_cache.is_stale() && !cli.reparse {
+        let songs_before = index_cache.songs.clone();
+        index_cache.reparse();
+        eprint!(
+            "Corrections or difficulty settings changed, reparsed cached posts: {}",
+            ChangeSummary::diff(&songs_before, &index_cache.songs)
+        );
+    }
+    if needs_save {
+        index_cache.save()?;
+    }
+
+    Ok((index_cache, lock))
+}
+
+fn doctor(index_cache: &IndexCache, json: bool, strict: bool) -> Result<()> {
+    let report = DoctorReport::diagnose(index_cache);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{report}");
+    }
+
+    // An error rather than `process::exit`, so the data lock is released
+    if strict && report.problem_count() > 0 {
+        return Err(anyhow!("Found {} problems", report.problem_count()));
+    }
+
+    Ok(())
+}
+
+fn merge_artists(
+    index_cache: IndexCache,
+    data_dir: &Path,
+    config: &Config,
+    apply: bool,
+) -> Result<()> {
+    let merges = artist_merge::find_artist_merges(&index_cache.songs);
+    if merges.is_empty() {
+        println!("No similar artist names found");
+        return Ok(());
+    }
+    for merge in &merges {
+        println!("{merge}");
+    }
+    if !apply {
+        println!("\nRun with --apply to add these to corrections.toml");
+        return Ok(());
+    }
+
+    Corrections::add_artist_aliases(
+        data_dir,
+        merges.iter().flat_map(|merge| {
+            merge
+                .variants
+                .iter()
+                .map(|variant| (variant.as_str(), merge.canonical.as_str()))
+        }),
+    )?;
+
+    let mut index_cache =
+        index_cache.with_parse_context(ParseContext::new(config, Corrections::load(data_dir)?));
+    let songs_before = index_cache.songs.clone();
+    index_cache.reparse();
+    eprint!(
+        "\nAdded to {}, reparsed cached posts: {}",
+        Corrections::path(data_dir).display(),
+        ChangeSummary::diff(&songs_before, &index_cache.songs)
+    );
+
+    index_cache.save()
+}
+
+fn export_cache(index_cache: &IndexCache, data_dir: &Path, file: &Path) -> Result<()> {
+    CacheExport::new(index_cache, data_dir)?.write(file)?;
+    println!(
+        "Exported {} posts to {}",
+        index_cache.posts.len(),
+        file.display()
+    );
+
+    Ok(())
+}
+
+/// Imports an export file into the cache. Runs without `load_index_cache`,
+/// since the cache it replaces or merges into may be unreadable.
+fn import_cache(data_dir: &Path, config: &Config, file: &Path, merge: bool) -> Result<()> {
+    let export = CacheExport::read(file)?;
+    let _lock = DataLock::acquire(data_dir)?;
+    let mut index_cache = IndexCache::load_or_empty(data_dir, config.storage.backend)?;
+
+    let songs_before = index_cache.songs.clone();
+    let summary = export.import(&mut index_cache, data_dir, merge)?;
+
+    // The import may have brought in corrections
+    let mut index_cache =
+        index_cache.with_parse_context(ParseContext::new(config, Corrections::load(data_dir)?));
+    index_cache.reparse();
+    index_cache.save()?;
+
+    print!(
+        "Imported {} new and {} updated posts, {} favorites: {}",
+        summary.posts_added,
+        summary.posts_replaced,
+        summary.favorites,
+        ChangeSummary::diff(&songs_before, &index_cache.songs)
+    );
+    if summary.corrections_imported {
+        println!("Wrote {}", Corrections::path(data_dir).display());
+    }
+    if summary.corrections_kept {
+        println!(
+            "Kept the existing {}; the exported corrections differ from it",
+            Corrections::path(data_dir).display()
+        );
+    }
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
-    let data_dir = create_data_dir()?;
-    let mut index_cache = IndexCache::load(&data_dir);
-    let coffee_api = CoffeeApi::new();
+    let data_dir = create_data_dir(&cli)?;
+    let config = load_config(&data_dir, &cli)?;
+    if let Some(Command::Cache {
+        command: CacheCommand::Import { file, merge },
+    }) = &cli.command
+    {
+        return import_cache(&data_dir, &config, file, *merge);
+    }
+    let (mut index_cache, _lock) = load_index_cache(&data_dir, &config, &cli)?;
+
+    match cli.command {
+        Some(Command::Doctor { json, strict }) => return doctor(&index_cache, json, strict),
+        Some(Command::MergeArtists { apply }) => {
+            return merge_artists(index_cache, &data_dir, &config, apply);
+        }
+        Some(Command::Cache {
+            command: CacheCommand::Export { file },
+        }) => return export_cache(&index_cache, &data_dir, &file),
+        // `cache import` ran before loading the cache
+        Some(Command::Cache { .. }) | None => {}
+    }
+
+    let mut coffee_api = CoffeeApi::new(&config)?;
+    if let Some(record_dir) = cli.record.clone() {
+        coffee_api = coffee_api.with_recording(record_dir);
+    }
+    if let Some(replay_dir) = cli.replay.clone() {
+        coffee_api = coffee_api.with_replay(replay_dir);
+    }
 
     if cli.check {
-        let response = coffee_api.get_posts(1, 1).await?;
-        let has_updates = response
-            .data
-            .first()
-            .map(|post| !index_cache.songs.iter().any(|s| s.id == post.id))
-            .unwrap_or(false);
-        println!("{}", serde_json::json!({ "has_updates": has_updates }));
+        let coffee_api = coffee_api.with_response_cache(ResponseCache::load(&data_dir));
+        let report = update::check(&coffee_api, &config.fetch, &index_cache).await?;
+        coffee_api.save_response_cache()?;
+
+        println!("{}", serde_json::to_string(&report)?);
         return Ok(());
     }
 
-    if index_cache.is_empty() || cli.update {
-        let highest_sequence_number = index_cache
-            .songs
-            .iter()
-            .flat_map(|s| s.sequence_number.parse::<usize>())
-            .max();
-        let mut page_number = 1;
-
-        loop {
-            print!("Fetching page {page_number}...");
-            io::stdout().flush()?;
-            let response: PageResponse<Post> = coffee_api.get_posts(page_number, 20).await?;
-            let page: Vec<_> = response.data.iter().flat_map(Song::try_from).collect();
-
-            let mut reached_existing_content = false;
-
-            // Only add songs we haven't already indexed
-            let new_songs = page.into_iter().filter(|s| {
-                if let Some(highest_sequence_number) = highest_sequence_number
-                    && let Ok(sequence_number) = s.sequence_number.parse::<usize>()
-                {
-                    let is_new = sequence_number > highest_sequence_number;
-
-                    // We've caught up to our index
-                    if !is_new {
-                        reached_existing_content = true;
-                    }
-
-                    is_new
-                } else {
-                    true
-                }
-            });
-
-            index_cache.songs.extend(new_songs);
-
-            println!(" done!");
-
-            if reached_existing_content || response.meta.current_page == response.meta.last_page {
-                break;
-            }
-
-            page_number += 1;
+    if cli.reparse && !index_cache.is_empty() {
+        let songs_before = index_cache.songs.clone();
+        let legacy_count = index_cache.reparse();
+        print!(
+            "Reparse complete: {}",
+            ChangeSummary::diff(&songs_before, &index_cache.songs)
+        );
+        if legacy_count > 0 {
+            println!(
+                "{legacy_count} songs were cached without their original post and were left as-is; run --resync to fetch them"
+            );
+        }
+
+        index_cache.save()?;
+    }
+
+    if cli.resync {
+        let summary = update::resync(&coffee_api, &config.fetch, &mut index_cache).await?;
+        print!("Resync complete: {summary}");
+
+        index_cache.last_indexed = Utc::now();
+        index_cache.save()?;
+    } else if index_cache.is_empty() || cli.update {
+        if index_cache.is_empty() {
+            update::full_index(&coffee_api, &config.fetch, &mut index_cache).await?;
+        } else {
+            update::incremental_update(&coffee_api, &config.fetch, &mut index_cache).await?;
         }
 
         index_cache.last_indexed = Utc::now();
         index_cache.save()?;
     }
 
+    let songs = filter_songs(&index_cache, &cli)?;
+    let groups = group_songs(
+        &songs,
+        index_cache.corrections(),
+        config.output.artist_order,
+        &Collation::new(config.output.locale.as_deref())?,
+    );
+
     if cli.xlsx
         && let Some(output_path) = cli.output
     {
         // XLSX format writes to a file instead of returning text-based result
-        XlsxFormatter::format_to_file(&index_cache.songs, &output_path)?;
+        XlsxFormatter::format_to_file(&groups, &output_path)?;
         println!("XLSX file saved to: {output_path}");
     } else if cli.pdf
         && let Some(output_path) = cli.output
     {
-        PdfFormatter::format_to_file(&index_cache.songs, index_cache.last_indexed, &output_path)?;
+        PdfFormatter::format_to_file(&groups, index_cache.last_indexed, &output_path)?;
         println!("PDF file saved to: {output_path}");
     } else {
         let file_type;
@@ -195,14 +621,14 @@ async fn main() -> Result<()> {
         // Text-based formats
         let formatted = if cli.markdown {
             file_type = "Markdown";
-            MarkdownFormatter::format(&index_cache.songs)?
+            MarkdownFormatter::format(&groups)?
         } else if cli.html {
             file_type = "HTML";
-            HtmlFormatter::format(&index_cache.songs, index_cache.last_indexed)
+            HtmlFormatter::format(&groups, index_cache.last_indexed)
         } else {
             // Default to JSON
             file_type = "JSON";
-            JsonFormatter::format(&index_cache.songs)?
+            JsonFormatter::format(&groups)?
         };
 
         if let Some(output_path) = cli.output {
@@ -1 +1,2 @@
+pub mod sequence_number;
 pub mod song;
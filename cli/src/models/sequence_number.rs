@@ -0,0 +1,123 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{Error, anyhow};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A song's number in the YouTube/PDF series, e.g. `42` or `42a` for a
+/// follow-up to #42. Orders by number first, then by suffix.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SequenceNumber {
+    pub number: u32,
+    /// Lowercase letters following the number, if any
+    pub suffix: String,
+}
+
+impl From<u32> for SequenceNumber {
+    fn from(number: u32) -> Self {
+        Self {
+            number,
+            suffix: String::new(),
+        }
+    }
+}
+
+impl FromStr for SequenceNumber {
+    type Err = Error;
+
+    /// Accepts `42`, `#42` and `42a`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix('#').unwrap_or(s);
+        let digits_end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (digits, suffix) = s.split_at(digits_end);
+
+        if digits.is_empty() || !suffix.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(anyhow!("Invalid sequence number \"{s}\""));
+        }
+
+        Ok(Self {
+            number: digits
+                .parse()
+                .map_err(|_| anyhow!("Sequence number \"{s}\" is too large"))?,
+            suffix: suffix.to_ascii_lowercase(),
+        })
+    }
+}
+
+impl Display for SequenceNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.number, self.suffix)
+    }
+}
+
+impl Serialize for SequenceNumber {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SequenceNumber {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// (De)serializes an optional sequence number as a plain string, with `""`
+/// for a missing one, which is what JSON consumers have always received.
+pub mod optional {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::SequenceNumber;
+
+    #[allow(clippy::ref_option)] // Signature required by `#[serde(with)]`
+    pub fn serialize<S: Serializer>(
+        sequence_number: &Option<SequenceNumber>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match sequence_number {
+            Some(sequence_number) => serializer.collect_str(sequence_number),
+            None => serializer.serialize_str(""),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SequenceNumber>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        if value.is_empty() {
+            return Ok(None);
+        }
+
+        value.parse().map(Some).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        let sequence_number: SequenceNumber = "#42A".parse().unwrap();
+        assert_eq!(sequence_number.number, 42);
+        assert_eq!(sequence_number.to_string(), "42a");
+
+        assert!("".parse::<SequenceNumber>().is_err());
+        assert!("#".parse::<SequenceNumber>().is_err());
+        assert!("42-1".parse::<SequenceNumber>().is_err());
+    }
+
+    #[test]
+    fn test_numeric_ordering() {
+        let mut sequence_numbers: Vec<SequenceNumber> = ["100", "9", "42a", "42"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        sequence_numbers.sort();
+
+        let sorted: Vec<_> = sequence_numbers.iter().map(ToString::to_string).collect();
+        assert_eq!(sorted, vec!["9", "42", "42a", "100"]);
+    }
+}
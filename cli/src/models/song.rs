@@ -1,14 +1,21 @@
 use std::fmt::Display;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+use crate::{
+    collation,
+    models::sequence_number::{self, SequenceNumber},
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
 pub enum Difficulty {
     Beginner,
     Intermediate,
     Advanced,
     Expert,
     Master,
+    #[default]
     Unrated,
 }
 
@@ -25,18 +32,193 @@ impl Display for Difficulty {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// How closely a heading matched the expected `Title - Artist | #N` layout
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    /// The artist or sequence number couldn't be found
+    #[default]
+    Low,
+    /// Everything was found, but only through one of the fallbacks
+    Medium,
+    /// The heading is in the standard layout
+    High,
+}
+
+impl Display for Confidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        };
+
+        write!(f, "{label}")
+    }
+}
+
+/// A tag from the post, e.g. a genre or decade
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub id: Option<usize>,
+    pub name: Option<String>,
+}
+
+impl Tag {
+    /// Whether `filter` is this tag's name (ignoring case and accents) or ID.
+    pub fn matches(&self, filter: &str) -> bool {
+        self.name
+            .as_deref()
+            .is_some_and(|name| collation::fold(name) == collation::fold(filter))
+            || self.id.is_some_and(|id| id.to_string() == filter)
+    }
+}
+
+impl Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.name, self.id) {
+            (Some(name), _) => write!(f, "{name}"),
+            (None, Some(id)) => write!(f, "{id}"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
 pub struct Song {
     pub id: usize,
+    /// Primary artist, without any `feat.` credit
     pub artist: String,
+    pub featured_artists: Vec<String>,
     pub title: String,
     pub difficulty: Difficulty,
     pub link: String,
-    pub sequence_number: String,
+    #[serde(with = "sequence_number::optional")]
+    pub sequence_number: Option<SequenceNumber>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Plain-text body of the post
+    pub body: String,
+    pub cover_image: Option<String>,
+    pub visibility: Option<String>,
+    pub members_only: bool,
+    pub tags: Vec<Tag>,
+    /// How reliably the heading was split into title, artist and number
+    pub parse_confidence: Confidence,
+}
+
+impl Song {
+    /// Title with the featured-artist credit, e.g. `Stan (feat. Dido)`
+    pub fn credited_title(&self) -> String {
+        if self.featured_artists.is_empty() {
+            self.title.clone()
+        } else {
+            format!(
+                "{} (feat. {})",
+                self.title,
+                self.featured_artists.join(", ")
+            )
+        }
+    }
+
+    /// Sequence number as displayed, e.g. `#42a`, or empty if there isn't one
+    pub fn sequence_label(&self) -> String {
+        self.sequence_number
+            .as_ref()
+            .map(|sequence_number| format!("#{sequence_number}"))
+            .unwrap_or_default()
+    }
+
+    /// When the post was last edited, if that was on a later day than it was
+    /// published; the grouped outputs only show updates worth noticing
+    pub fn revised_at(&self) -> Option<DateTime<Utc>> {
+        self.updated_at.filter(|updated_at| {
+            self.published_at
+                .is_none_or(|published_at| updated_at.date_naive() > published_at.date_naive())
+        })
+    }
+
+    /// Names of the song's tags, or their IDs when unnamed
+    pub fn tag_labels(&self) -> Vec<String> {
+        self.tags.iter().map(ToString::to_string).collect()
+    }
+
+    /// Whether `name` is the primary or a featured artist, ignoring case and
+    /// accents.
+    pub fn has_artist(&self, name: &str) -> bool {
+        let name = collation::fold(name);
+
+        collation::fold(&self.artist) == name
+            || self
+                .featured_artists
+                .iter()
+                .any(|featured| collation::fold(featured) == name)
+    }
 }
 
 #[derive(Debug, Serialize)]
 pub struct SongGroup {
     pub artist: String,
+    /// Name the group is sorted under, e.g. `Beatles` for `The Beatles`
+    pub sort_name: String,
     pub songs: Vec<Song>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_featured_artists() {
+        let song = Song {
+            artist: "Eminem".to_owned(),
+            featured_artists: vec!["Dido".to_owned()],
+            title: "Stan".to_owned(),
+            ..Song::default()
+        };
+
+        assert_eq!(song.credited_title(), "Stan (feat. Dido)");
+        assert!(song.has_artist("eminem"));
+        assert!(song.has_artist("DIDO"));
+        assert!(song.has_artist("Emínem"));
+        assert!(!song.has_artist("Dido Armstrong"));
+    }
+
+    #[test]
+    fn test_revised_at_ignores_same_day_edits() {
+        let date = |timestamp| DateTime::from_timestamp(timestamp, 0);
+        let song = |published_at, updated_at| Song {
+            published_at,
+            updated_at,
+            ..Song::default()
+        };
+
+        // 2024-03-01 18:30 and 22:00, then 2024-03-02 09:00 UTC
+        assert_eq!(
+            song(date(1_709_317_800), date(1_709_330_400)).revised_at(),
+            None
+        );
+        assert_eq!(
+            song(date(1_709_317_800), date(1_709_370_000)).revised_at(),
+            date(1_709_370_000)
+        );
+        assert_eq!(
+            song(None, date(1_709_370_000)).revised_at(),
+            date(1_709_370_000)
+        );
+        assert_eq!(song(date(1_709_317_800), None).revised_at(), None);
+    }
+
+    #[test]
+    fn test_tag_matches_name_or_id() {
+        let tag = Tag {
+            id: Some(9),
+            name: Some("Punk".to_owned()),
+        };
+
+        assert!(tag.matches("punk"));
+        assert!(tag.matches("9"));
+        assert!(!tag.matches("Pop Punk"));
+        assert_eq!(tag.to_string(), "Punk");
+    }
+}
@@ -1,19 +1,14 @@
 use chrono::{DateTime, Utc};
 use maud::{DOCTYPE, PreEscaped, html};
 
-use crate::{
-    group_songs,
-    models::song::{Difficulty, Song},
-};
+use crate::models::song::{Difficulty, SongGroup};
 
 const STYLES: &str = include_str!("styles.css");
 
 pub struct HtmlFormatter;
 
 impl HtmlFormatter {
-    pub fn format(songs: &[Song], last_indexed: DateTime<Utc>) -> String {
-        let groups = group_songs(songs);
-
+    pub fn format(groups: &[SongGroup], last_indexed: DateTime<Utc>) -> String {
         let markup = html! {
             (DOCTYPE)
             html {
@@ -32,7 +27,7 @@ impl HtmlFormatter {
                     p.timestamp {
                         span.timestamp-label { "Last indexed:" } (last_indexed.format("%v %r %Z"))
                     }
-                    @for group in &groups {
+                    @for group in groups {
                         div.artist-group {
                             div.artist-header { (group.artist) }
                             table.song-table {
@@ -46,9 +41,40 @@ impl HtmlFormatter {
                                         Difficulty::Unrated => "—",
                                     };
                                     tr.item.song-item {
-                                        td.song-title { (song.title) }
+                                        td.song-title {
+                                            (song.title)
+                                            @if !song.featured_artists.is_empty() {
+                                                span.song-featured {
+                                                    "feat. " (song.featured_artists.join(", "))
+                                                }
+                                            }
+                                            @if song.members_only {
+                                                span.members-only title="Members only" { "Members" }
+                                            }
+                                            @for label in song.tag_labels() {
+                                                span.song-tag { (label) }
+                                            }
+                                            @if let Some(cover_image) = &song.cover_image {
+                                                a.song-cover href=(cover_image) { "Cover" }
+                                            }
+                                        }
+                                        td.song-date {
+                                            @if let Some(published_at) = song.published_at {
+                                                time datetime=(published_at.to_rfc3339()) {
+                                                    (published_at.format("%Y-%m-%d"))
+                                                }
+                                            }
+                                            @if let Some(revised_at) = song.revised_at() {
+                                                span.song-updated {
+                                                    "Updated "
+                                                    time datetime=(revised_at.to_rfc3339()) {
+                                                        (revised_at.format("%Y-%m-%d"))
+                                                    }
+                                                }
+                                            }
+                                        }
                                         td.song-difficulty { (stars) }
-                                        td.song-number { "#" (song.sequence_number) }
+                                        td.song-number { (song.sequence_label()) }
                                     }
                                 }
                             }
@@ -1,14 +1,13 @@
 use anyhow::Result;
 use serde_json;
 
-use crate::{group_songs, models::song::Song};
+use crate::models::song::SongGroup;
 
 pub struct JsonFormatter;
 
 impl JsonFormatter {
-    pub fn format(songs: &[Song]) -> Result<String> {
-        let groups = group_songs(songs);
-        let json = serde_json::to_string_pretty(&groups)?;
+    pub fn format(groups: &[SongGroup]) -> Result<String> {
+        let json = serde_json::to_string_pretty(groups)?;
         Ok(json)
     }
 }
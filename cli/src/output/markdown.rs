@@ -1,24 +1,37 @@
 use anyhow::Result;
 
-use crate::{group_songs, models::song::Song};
+use crate::models::song::SongGroup;
 use std::fmt::Write;
 
 pub struct MarkdownFormatter;
 
 impl MarkdownFormatter {
-    pub fn format(songs: &[Song]) -> Result<String> {
-        let groups = group_songs(songs);
-
+    pub fn format(groups: &[SongGroup]) -> Result<String> {
         let mut result = String::new();
 
-        for group in &groups {
+        for group in groups {
             writeln!(result, "# {}", group.artist)?;
             for song in &group.songs {
-                writeln!(
+                write!(
                     result,
-                    "- {} | #{} | {}",
-                    song.title, song.sequence_number, song.difficulty
+                    "- {} | {} | {}",
+                    song.credited_title(),
+                    song.sequence_label(),
+                    song.difficulty
                 )?;
+                if let Some(published_at) = song.published_at {
+                    write!(result, " | {}", published_at.format("%Y-%m-%d"))?;
+                }
+                if let Some(revised_at) = song.revised_at() {
+                    write!(result, " | Updated {}", revised_at.format("%Y-%m-%d"))?;
+                }
+                if song.members_only {
+                    write!(result, " | Members only")?;
+                }
+                if let Some(cover_image) = &song.cover_image {
+                    write!(result, " | [Cover]({cover_image})")?;
+                }
+                writeln!(result)?;
             }
 
             writeln!(result)?;
@@ -12,10 +12,7 @@ use printpdf::{
     PolygonMode, Rgb, WindingOrder,
 };
 
-use crate::{
-    group_songs,
-    models::song::{Difficulty, Song},
-};
+use crate::models::song::{Difficulty, SongGroup};
 
 // Page dimensions (US Letter)
 const PAGE_W: f64 = 215.9;
@@ -41,7 +38,8 @@ const BEZIER_K: f64 = 0.552_284_75;
 // Song row column zones (within COL_W)
 const SEQ_ZONE: f64 = 12.0; // "#437" — controls gap between stars and seq number
 const STARS_ZONE: f64 = 14.0; // "★★★" right-aligned
-const TITLE_ZONE: f64 = COL_W - STARS_ZONE - SEQ_ZONE;
+const DATE_ZONE: f64 = 17.0; // "2024-03-01" published date
+const TITLE_ZONE: f64 = COL_W - DATE_ZONE - STARS_ZONE - SEQ_ZONE;
 
 // Extra space below artist header bar before first song row
 const ARTIST_BOTTOM_PAD: f64 = 1.5;
@@ -51,6 +49,8 @@ const PT_TITLE: f32 = 20.0;
 const PT_SUBTITLE: f32 = 9.0;
 const PT_ARTIST: f32 = 10.0;
 const PT_SONG: f32 = 9.0;
+const PT_DATE: f32 = 7.5;
+const PT_UPDATED: f32 = 6.0;
 
 // System font paths (macOS)
 const FONT_REGULAR: &str = "/System/Library/Fonts/Supplemental/Arial.ttf";
@@ -60,32 +60,39 @@ const FONT_SYMBOL: &str = "/System/Library/Fonts/SFCompact.ttf"; // supports ★
 // Padding within cells
 const H_PAD: f64 = 1.5; // horizontal left padding for text
 
+// Appended to titles of members-only transcriptions (in Arial, unlike ★)
+const MEMBERS_ONLY_MARK: &str = "†";
+
 // Page 1 header section height (title + subtitle + gap before columns)
 const PAGE1_HEADER_H: f64 = 22.0;
 
+struct SongRow {
+    title_lines: Vec<String>,
+    stars: &'static str,
+    /// True when stars is "—" (use regular font instead of symbol)
+    is_unrated: bool,
+    /// Published date, or empty when unknown
+    published: String,
+    /// "upd." and the date of a later edit, drawn below `published`, or empty
+    updated: String,
+    seq_num: String,
+    /// Offset from column left where "#" should be drawn, so the widest
+    /// number in the group ends H_PAD from the right edge.
+    seq_col_offset: f64,
+    is_alternate: bool,
+    height_mm: f64,
+}
+
 enum LayoutItem {
-    ArtistHeader {
-        artist: String,
-    },
-    SongRow {
-        title_lines: Vec<String>,
-        stars: &'static str,
-        /// True when stars is "—" (use regular font instead of symbol)
-        is_unrated: bool,
-        seq_num: String,
-        /// Offset from column left where "#" should be drawn, so the widest
-        /// number in the group ends H_PAD from the right edge.
-        seq_col_offset: f64,
-        is_alternate: bool,
-        height_mm: f64,
-    },
+    ArtistHeader { artist: String },
+    SongRow(SongRow),
 }
 
 impl LayoutItem {
     fn height_mm(&self) -> f64 {
         match self {
             Self::ArtistHeader { .. } => ARTIST_H + ARTIST_BOTTOM_PAD,
-            Self::SongRow { height_mm, .. } => *height_mm,
+            Self::SongRow(row) => row.height_mm,
         }
     }
 }
@@ -138,6 +145,12 @@ fn pdf_y(y_from_top: f64) -> Mm {
     Mm((PAGE_H - y_from_top) as f32)
 }
 
+/// Page coordinates are a few hundred millimetres, well within f32's range.
+#[allow(clippy::cast_possible_truncation)]
+fn pdf_x(x_mm: f64) -> Mm {
+    Mm(x_mm as f32)
+}
+
 fn load_font(path: &str) -> Result<Vec<u8>> {
     std::fs::read(path).with_context(|| format!("Failed to load font: {path}"))
 }
@@ -204,8 +217,7 @@ fn difficulty_stars(d: &Difficulty) -> (&'static str, bool) {
     }
 }
 
-fn build_layout(songs: &[Song], fonts: &Fonts) -> Vec<LayoutItem> {
-    let groups = group_songs(songs);
+fn build_layout(groups: &[SongGroup], fonts: &Fonts) -> Vec<LayoutItem> {
     let mut items = Vec::new();
 
     for group in groups {
@@ -217,24 +229,39 @@ fn build_layout(songs: &[Song], fonts: &Fonts) -> Vec<LayoutItem> {
         let max_seq_width = group
             .songs
             .iter()
-            .map(|s| measure_mm(&fonts.ab_regular, &format!("#{}", s.sequence_number), PT_SONG))
+            .map(|s| measure_mm(&fonts.ab_regular, &s.sequence_label(), PT_SONG))
             .fold(0.0_f64, f64::max);
         let seq_col_offset = COL_W - H_PAD - max_seq_width;
 
         for (i, song) in group.songs.iter().enumerate() {
-            let title_lines =
-                wrap_text(&fonts.ab_regular, &song.title, PT_SONG, TITLE_ZONE - H_PAD);
-            let line_count = title_lines.len().max(1);
+            let title = if song.members_only {
+                format!("{} {MEMBERS_ONLY_MARK}", song.credited_title())
+            } else {
+                song.credited_title()
+            };
+            let title_lines = wrap_text(&fonts.ab_regular, &title, PT_SONG, TITLE_ZONE - H_PAD);
+            let updated = song
+                .revised_at()
+                .map(|date| date.format("upd. %Y-%m-%d").to_string())
+                .unwrap_or_default();
+            // The update goes on a second line, below the published date
+            let min_lines = if updated.is_empty() { 1 } else { 2 };
+            let line_count = title_lines.len().max(min_lines);
             let (stars, is_unrated) = difficulty_stars(&song.difficulty);
-            items.push(LayoutItem::SongRow {
+            items.push(LayoutItem::SongRow(SongRow {
                 height_mm: ROW_H * line_count as f64,
                 title_lines,
                 stars,
                 is_unrated,
-                seq_num: song.sequence_number.clone(),
+                published: song
+                    .published_at
+                    .map(|date| date.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default(),
+                updated,
+                seq_num: song.sequence_label(),
                 seq_col_offset,
                 is_alternate: i % 2 == 1,
-            });
+            }));
         }
     }
 
@@ -329,9 +356,9 @@ fn draw_page_header(layer: &PdfLayerReference, fonts: &Fonts, last_indexed: Date
         &fonts.bold,
     );
 
-    // Subtitle: "Last indexed: ..." regular 9pt
+    // Subtitle: "Last indexed: ...    † Members only" regular 9pt
     let subtitle = format!(
-        "Last indexed: {}",
+        "Last indexed: {}    {MEMBERS_ONLY_MARK} Members only",
         last_indexed.format("%-d-%b-%Y %-I:%M:%S %p %Z")
     );
     layer.use_text(
@@ -368,25 +395,25 @@ fn draw_artist_header(
     );
 }
 
-fn draw_song_row(
-    layer: &PdfLayerReference,
-    fonts: &Fonts,
-    cursor: &LayoutCursor,
-    title_lines: &[String],
-    stars: &str,
-    is_unrated: bool,
-    seq_num: &str,
-    seq_col_offset: f64,
-    is_alternate: bool,
-    height_mm: f64,
-) {
+fn draw_song_row(layer: &PdfLayerReference, fonts: &Fonts, cursor: &LayoutCursor, row: &SongRow) {
+    let SongRow {
+        title_lines,
+        stars,
+        is_unrated,
+        published,
+        updated,
+        seq_num,
+        seq_col_offset,
+        is_alternate,
+        height_mm,
+    } = row;
     let x = cursor.col_x();
     let y_top = cursor.y_mm;
 
     // Alternating row background
-    if is_alternate {
+    if *is_alternate {
         layer.set_fill_color(Color::Rgb(Rgb::new(0.961, 0.961, 0.961, None)));
-        filled_rect(layer, x, y_top, COL_W, height_mm);
+        filled_rect(layer, x, y_top, COL_W, *height_mm);
     }
 
     // Draw each wrapped title line
@@ -404,19 +431,39 @@ fn draw_song_row(
 
     let first_line_y = y_top + ROW_H * 0.65;
 
+    // Published date, and any later update below it — left-aligned in date zone; color #666
+    layer.set_fill_color(Color::Rgb(Rgb::new(0.4, 0.4, 0.4, None)));
+    layer.use_text(
+        published.as_str(),
+        PT_DATE,
+        pdf_x(x + TITLE_ZONE),
+        pdf_y(first_line_y),
+        &fonts.regular,
+    );
+    if !updated.is_empty() {
+        layer.use_text(
+            updated.as_str(),
+            PT_UPDATED,
+            pdf_x(x + TITLE_ZONE),
+            pdf_y(first_line_y + ROW_H),
+            &fonts.regular,
+        );
+    }
+    layer.set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+
     // Stars / dash — right-aligned in stars zone (first line only).
     // Use regular font for "—" (em dash is in Arial); symbol font for ★.
-    let stars_font = if is_unrated { &fonts.regular } else { &fonts.symbol };
-    let stars_ab = if is_unrated {
+    let stars_font = if *is_unrated { &fonts.regular } else { &fonts.symbol };
+    let stars_ab = if *is_unrated {
         &fonts.ab_regular
     } else {
         &fonts.ab_symbol
     };
-    let stars_zone_x = x + TITLE_ZONE;
+    let stars_zone_x = x + TITLE_ZONE + DATE_ZONE;
     let stars_width = measure_mm(stars_ab, stars, PT_SONG);
     let stars_x = stars_zone_x + STARS_ZONE - stars_width - 0.5;
     layer.use_text(
-        stars,
+        *stars,
         PT_SONG,
         Mm(stars_x as f32),
         pdf_y(first_line_y),
@@ -427,7 +474,7 @@ fn draw_song_row(
     layer.set_fill_color(Color::Rgb(Rgb::new(0.4, 0.4, 0.4, None)));
     let seq_x = x + seq_col_offset;
     layer.use_text(
-        format!("#{seq_num}"),
+        seq_num.as_str(),
         PT_SONG,
         Mm(seq_x as f32),
         pdf_y(first_line_y),
@@ -438,7 +485,11 @@ fn draw_song_row(
 pub struct PdfFormatter;
 
 impl PdfFormatter {
-    pub fn format_to_file(songs: &[Song], last_indexed: DateTime<Utc>, path: &str) -> Result<()> {
+    pub fn format_to_file(
+        groups: &[SongGroup],
+        last_indexed: DateTime<Utc>,
+        path: &str,
+    ) -> Result<()> {
         // Load font bytes
         let regular_bytes = load_font(FONT_REGULAR)?;
         let bold_bytes = load_font(FONT_BOLD)?;
@@ -474,7 +525,7 @@ impl PdfFormatter {
         };
 
         // Pre-calculate layout
-        let items = build_layout(songs, &fonts);
+        let items = build_layout(groups, &fonts);
 
         // Draw page 1 header
         let layer = doc.get_page(page1).get_layer(layer1);
@@ -519,27 +570,8 @@ impl PdfFormatter {
                 LayoutItem::ArtistHeader { artist } => {
                     draw_artist_header(&current_layer, &fonts, &cursor, artist);
                 }
-                LayoutItem::SongRow {
-                    title_lines,
-                    stars,
-                    is_unrated,
-                    seq_num,
-                    seq_col_offset,
-                    is_alternate,
-                    height_mm,
-                } => {
-                    draw_song_row(
-                        &current_layer,
-                        &fonts,
-                        &cursor,
-                        title_lines,
-                        stars,
-                        *is_unrated,
-                        seq_num,
-                        *seq_col_offset,
-                        *is_alternate,
-                        *height_mm,
-                    );
+                LayoutItem::SongRow(row) => {
+                    draw_song_row(&current_layer, &fonts, &cursor, row);
                 }
             }
 
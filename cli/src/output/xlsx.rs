@@ -1,17 +1,106 @@
 use anyhow::Result;
-use rust_xlsxwriter::{Color, Format, Url, Workbook};
+use chrono::{DateTime, Utc};
+use chrono::{Datelike, Timelike};
+use rust_xlsxwriter::{Color, ExcelDateTime, Format, FormatAlign, Url, Workbook, Worksheet};
+
+use crate::models::song::{Difficulty, Song, SongGroup};
+
+/// Longest URL Excel stores in a hyperlink, in characters
+const MAX_URL_LENGTH: usize = 2_080;
+
+/// Whether `url` can be written as a hyperlink. Anything else, like a
+/// relative or over-long cover image URL, is written as plain text rather
+/// than failing the whole export.
+fn is_linkable(url: &str) -> bool {
+    ["http://", "https://", "ftp://", "ftps://"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+        && url.chars().count() <= MAX_URL_LENGTH
+}
+
+fn excel_date(date: DateTime<Utc>) -> Result<ExcelDateTime> {
+    Ok(ExcelDateTime::from_ymd(
+        u16::try_from(date.year())?,
+        u8::try_from(date.month())?,
+        u8::try_from(date.day())?,
+    )?
+    .and_hms(
+        u16::try_from(date.hour())?,
+        u8::try_from(date.minute())?,
+        date.second(),
+    )?)
+}
+
+/// Writes plain sequence numbers as numbers so the column sorts numerically;
+/// ones with a suffix, like `42a`, are written as text.
+fn write_sequence_number(
+    worksheet: &mut Worksheet,
+    row: u32,
+    song: &Song,
+    row_format: &Format,
+) -> Result<()> {
+    match &song.sequence_number {
+        Some(sequence_number) if sequence_number.suffix.is_empty() => {
+            worksheet.write_number_with_format(
+                row,
+                2,
+                sequence_number.number,
+                &row_format.clone().set_align(FormatAlign::Left),
+            )?;
+        }
+        Some(sequence_number) => {
+            worksheet.write_string_with_format(row, 2, sequence_number.to_string(), row_format)?;
+        }
+        None => {
+            worksheet.write_blank(row, 2, row_format)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the published and updated dates, members-only flag, cover image and
+/// tag columns.
+fn write_metadata(
+    worksheet: &mut Worksheet,
+    row: u32,
+    song: &Song,
+    row_format: &Format,
+    date_format: &Format,
+    hyperlink_format: &Format,
+) -> Result<()> {
+    for (column, date) in [(3, song.published_at), (4, song.updated_at)] {
+        if let Some(date) = date {
+            worksheet.write_datetime_with_format(row, column, excel_date(date)?, date_format)?;
+        } else {
+            worksheet.write_blank(row, column, row_format)?;
+        }
+    }
+
+    if song.members_only {
+        worksheet.write_with_format(row, 5, "Yes", row_format)?;
+    } else {
+        worksheet.write_blank(row, 5, row_format)?;
+    }
+
+    if let Some(cover_image) = song.cover_image.as_deref().filter(|url| is_linkable(url)) {
+        worksheet.write_url_with_format(row, 6, Url::new(cover_image), hyperlink_format)?;
+    } else if let Some(cover_image) = &song.cover_image {
+        worksheet.write_string_with_format(row, 6, cover_image, row_format)?;
+    } else {
+        worksheet.write_blank(row, 6, row_format)?;
+    }
+
+    worksheet.write_with_format(row, 7, song.tag_labels().join(", "), row_format)?;
 
-use crate::{
-    group_songs,
-    models::song::{Difficulty, Song},
-};
+    Ok(())
+}
 
 pub struct XlsxFormatter;
 
 impl XlsxFormatter {
-    pub fn format_to_file(songs: &[Song], path: &str) -> Result<()> {
-        let groups = group_songs(songs);
-
+    #[allow(clippy::too_many_lines)]
+    pub fn format_to_file(groups: &[SongGroup], path: &str) -> Result<()> {
         let mut workbook = Workbook::new();
         let worksheet = workbook.add_worksheet();
 
@@ -31,26 +120,40 @@ impl XlsxFormatter {
         let alternate_row_hyperlink_format = Format::default()
             .set_background_color(Color::RGB(0xF5_F5_F5))
             .set_hyperlink();
+        let plain_format = Format::default();
+        let plain_hyperlink_format = Format::default().set_hyperlink();
+        let date_format = Format::default().set_num_format("yyyy-mm-dd");
+        let alternate_row_date_format = alternate_row_format.clone().set_num_format("yyyy-mm-dd");
 
         // Set column widths
         worksheet.set_column_width(0, 40)?; // Title
         worksheet.set_column_width(1, 20)?; // Difficulty
         worksheet.set_column_width(2, 12)?; // Sequence Number
+        worksheet.set_column_width(3, 12)?; // Published
+        worksheet.set_column_width(4, 12)?; // Updated
+        worksheet.set_column_width(5, 14)?; // Members only
+        worksheet.set_column_width(6, 40)?; // Cover image
+        worksheet.set_column_width(7, 30)?; // Tags
 
         // Write header row
         worksheet.write_with_format(0, 0, "Title", &header_format)?;
         worksheet.write_with_format(0, 1, "Difficulty", &header_format)?;
         worksheet.write_with_format(0, 2, "Sequence #", &header_format)?;
+        worksheet.write_with_format(0, 3, "Published", &header_format)?;
+        worksheet.write_with_format(0, 4, "Updated", &header_format)?;
+        worksheet.write_with_format(0, 5, "Members only", &header_format)?;
+        worksheet.write_with_format(0, 6, "Cover image", &header_format)?;
+        worksheet.write_with_format(0, 7, "Tags", &header_format)?;
 
         let mut current_row = 1u32;
 
-        for group in &groups {
+        for group in groups {
             // Write artist header
             worksheet.merge_range(
                 current_row,
                 0,
                 current_row,
-                2,
+                7,
                 &group.artist,
                 &artist_header_format,
             )?;
@@ -72,7 +175,7 @@ impl XlsxFormatter {
                     worksheet.write_url_with_format(
                         current_row,
                         0,
-                        Url::new(&song.link).set_text(&song.title),
+                        Url::new(&song.link).set_text(song.credited_title()),
                         &alternate_row_hyperlink_format,
                     )?;
                     worksheet.write_with_format(
@@ -81,22 +184,35 @@ impl XlsxFormatter {
                         difficulty_str,
                         &alternate_row_format,
                     )?;
-                    worksheet.write_with_format(
-                        current_row,
-                        2,
-                        &song.sequence_number,
-                        &alternate_row_format,
-                    )?;
                 } else {
                     worksheet.write_url(
                         current_row,
                         0,
-                        Url::new(&song.link).set_text(&song.title),
+                        Url::new(&song.link).set_text(song.credited_title()),
                     )?;
                     worksheet.write(current_row, 1, difficulty_str)?;
-                    worksheet.write(current_row, 2, &song.sequence_number)?;
                 }
 
+                let (row_format, row_date_format, row_hyperlink_format) = if idx % 2 == 1 {
+                    (
+                        &alternate_row_format,
+                        &alternate_row_date_format,
+                        &alternate_row_hyperlink_format,
+                    )
+                } else {
+                    (&plain_format, &date_format, &plain_hyperlink_format)
+                };
+
+                write_sequence_number(worksheet, current_row, song, row_format)?;
+                write_metadata(
+                    worksheet,
+                    current_row,
+                    song,
+                    row_format,
+                    row_date_format,
+                    row_hyperlink_format,
+                )?;
+
                 current_row += 1;
             }
 
@@ -109,3 +225,19 @@ impl XlsxFormatter {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_linkable() {
+        assert!(is_linkable("https://cdn.example.com/cover.jpg"));
+        assert!(!is_linkable("/uploads/cover.jpg"));
+        assert!(!is_linkable("cdn.example.com/cover.jpg"));
+        assert!(!is_linkable(&format!(
+            "https://cdn.example.com/{}",
+            "a".repeat(MAX_URL_LENGTH)
+        )));
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    fs::{self, File, TryLockError},
+    io::{ErrorKind, Write},
+    path::Path,
+    process,
+};
+
+use anyhow::{Context, Result};
+
+const LOCK_FILENAME: &str = "index.lock";
+
+/// Writes `contents` to a temporary file next to `path` and renames it over
+/// `path`, so a crash or a concurrent reader never sees a partial file.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_owned();
+    temp_name.push(format!(".tmp.{}", process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let result = (|| {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result.with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Reads a text file that doesn't have to exist: `None` if it doesn't, and an
+/// error for anything else that keeps it from being read.
+pub fn read_optional(path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// An exclusive advisory lock on the data directory, held by any run that
+/// may update the cache so overlapping runs take turns. Released on drop.
+pub struct DataLock {
+    _file: File,
+}
+
+impl DataLock {
+    /// Takes the lock, waiting for whichever run holds it to finish.
+    pub fn acquire(data_dir: &Path) -> Result<Self> {
+        let path = data_dir.join(LOCK_FILENAME);
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create lock file: {}", path.display()))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                eprintln!("Waiting for another drumscribe-index run to finish...");
+                file.lock()
+                    .with_context(|| format!("Failed to lock {}", path.display()))?;
+            }
+            Err(TryLockError::Error(error)) => {
+                return Err(error).with_context(|| format!("Failed to lock {}", path.display()));
+            }
+        }
+
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support;
+
+    use super::*;
+
+    #[test]
+    fn test_write_atomic_replaces_file() {
+        let dir = test_support::temp_dir();
+        let path = dir.path().join("index.bin");
+
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_read_optional() {
+        let dir = test_support::temp_dir();
+        let path = dir.path().join("config.toml");
+
+        assert_eq!(read_optional(&path).unwrap(), None);
+        fs::write(&path, "x = 1").unwrap();
+        assert_eq!(read_optional(&path).unwrap().as_deref(), Some("x = 1"));
+
+        // Anything but a missing file is an error, not an empty default
+        fs::write(&path, b"\xff\xfe").unwrap();
+        assert!(read_optional(&path).is_err());
+        assert!(read_optional(dir.path()).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+//! Fixtures shared by the unit tests.
+
+use serde_json::{Value, json};
+use tempfile::TempDir;
+
+use crate::api::post::RawPost;
+
+/// A fresh directory, deleted with everything in it when dropped.
+pub fn temp_dir() -> TempDir {
+    tempfile::Builder::new()
+        .prefix("drumscribe-index-test-")
+        .tempdir()
+        .unwrap()
+}
+
+/// A post as the API returns it, with only the fields parsing needs; add
+/// more with [`PostJson::with`].
+pub struct PostJson(Value);
+
+impl PostJson {
+    pub fn new(id: usize, heading: &str) -> Self {
+        Self(json!({
+            "id": id,
+            "project_update_heading": heading,
+            "tags": [],
+            "share_urls": { "copyUrl": format!("https://example.com/{id}") },
+        }))
+    }
+
+    /// Sets a field, replacing any value it had.
+    pub fn with(mut self, field: &str, value: impl Into<Value>) -> Self {
+        self.0[field] = value.into();
+        self
+    }
+
+    /// Adds a tag for each difficulty category ID.
+    pub fn with_categories(self, category_ids: &[usize]) -> Self {
+        let tags: Vec<_> = category_ids
+            .iter()
+            .map(|id| json!({ "category_id": id }))
+            .collect();
+        self.with("tags", tags)
+    }
+
+    pub fn build(self) -> Value {
+        self.0
+    }
+
+    pub fn raw(self) -> RawPost {
+        RawPost::new(self.0.to_string()).unwrap()
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    io::{self, Write},
+};
+
+use anyhow::Result;
+use futures::{StreamExt, stream};
+use serde::Serialize;
+
+use crate::{
+    api::{
+        coffee_api::{CoffeeApi, PageResponse},
+        post::RawPost,
+    },
+    config::FetchConfig,
+    index_cache::IndexCache,
+    models::song::Song,
+};
+
+/// Fetches every post in the catalog. The first page tells us how many pages
+/// exist, the rest are requested concurrently but merged in page order.
+pub async fn fetch_all_posts(
+    coffee_api: &CoffeeApi,
+    fetch_config: &FetchConfig,
+) -> Result<Vec<RawPost>> {
+    let page_size = fetch_config.page_size.max(1);
+
+    println!("Fetching page 1...");
+    let first_page = coffee_api.get_posts(1, page_size).await?;
+    let last_page = first_page.meta.last_page;
+
+    let mut pages = stream::iter(2..=last_page)
+        .map(|page_number| coffee_api.get_posts(page_number, page_size))
+        .buffered(fetch_config.concurrency.max(1));
+
+    let mut responses = vec![first_page];
+    while let Some(response) = pages.next().await {
+        let response = response?;
+        println!("Fetched page {} of {last_page}", response.meta.current_page);
+        responses.push(response);
+    }
+
+    Ok(merge_pages(responses))
+}
+
+/// Concatenates pages, dropping posts that shifted onto a later page because
+/// something was published while we were fetching.
+fn merge_pages(responses: Vec<PageResponse<RawPost>>) -> Vec<RawPost> {
+    let mut seen = HashSet::new();
+
+    responses
+        .into_iter()
+        .flat_map(|response| response.data)
+        .filter(|post| seen.insert(post.id))
+        .collect()
+}
+
+/// Builds the index from scratch.
+pub async fn full_index(
+    coffee_api: &CoffeeApi,
+    fetch_config: &FetchConfig,
+    index_cache: &mut IndexCache,
+) -> Result<()> {
+    let posts = fetch_all_posts(coffee_api, fetch_config).await?;
+    index_cache.set_posts(posts);
+
+    Ok(())
+}
+
+/// Songs whose posts were edited since they were indexed
+pub struct SongUpdate {
+    pub before: Song,
+    pub after: Song,
+}
+
+impl SongUpdate {
+    fn changed_fields(&self) -> Vec<&'static str> {
+        let (before, after) = (&self.before, &self.after);
+        let mut fields = vec![];
+
+        if before.title != after.title {
+            fields.push("title");
+        }
+        if before.artist != after.artist || before.featured_artists != after.featured_artists {
+            fields.push("artist");
+        }
+        if before.difficulty != after.difficulty {
+            fields.push("difficulty");
+        }
+        if before.sequence_number != after.sequence_number {
+            fields.push("sequence number");
+        }
+        if before.link != after.link {
+            fields.push("link");
+        }
+        if before.published_at != after.published_at || before.updated_at != after.updated_at {
+            fields.push("dates");
+        }
+        if before.body != after.body {
+            fields.push("body");
+        }
+        if before.cover_image != after.cover_image {
+            fields.push("cover image");
+        }
+        if before.tags != after.tags {
+            fields.push("tags");
+        }
+        if before.visibility != after.visibility || before.members_only != after.members_only {
+            fields.push("visibility");
+        }
+
+        fields
+    }
+}
+
+#[derive(Default)]
+pub struct ChangeSummary {
+    pub added: Vec<Song>,
+    pub updated: Vec<SongUpdate>,
+    pub removed: Vec<Song>,
+}
+
+impl ChangeSummary {
+    /// Compares two versions of the catalog, keyed by post ID.
+    pub fn diff(before: &[Song], after: &[Song]) -> Self {
+        let before_by_id: HashMap<_, _> = before.iter().map(|song| (song.id, song)).collect();
+        let after_ids: HashSet<_> = after.iter().map(|song| song.id).collect();
+        let mut summary = ChangeSummary::default();
+
+        for song in after {
+            match before_by_id.get(&song.id) {
+                None => summary.added.push(song.clone()),
+                Some(&before_song) if before_song != song => summary.updated.push(SongUpdate {
+                    before: before_song.clone(),
+                    after: song.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+
+        summary.removed = before
+            .iter()
+            .filter(|song| !after_ids.contains(&song.id))
+            .cloned()
+            .collect();
+
+        summary
+    }
+}
+
+impl Display for ChangeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} added, {} updated, {} removed",
+            self.added.len(),
+            self.updated.len(),
+            self.removed.len()
+        )?;
+
+        for song in &self.added {
+            writeln!(f, "  + {} - {}", song.title, song.artist)?;
+        }
+        for update in &self.updated {
+            writeln!(
+                f,
+                "  ~ {} - {} → {} - {} ({})",
+                update.before.title,
+                update.before.artist,
+                update.after.title,
+                update.after.artist,
+                update.changed_fields().join(", ")
+            )?;
+        }
+        for song in &self.removed {
+            writeln!(f, "  - {} - {}", song.title, song.artist)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Re-fetches the whole catalog and replaces the cached songs with it, picking
+/// up edited and deleted posts that an incremental update never revisits.
+pub async fn resync(
+    coffee_api: &CoffeeApi,
+    fetch_config: &FetchConfig,
+    index_cache: &mut IndexCache,
+) -> Result<ChangeSummary> {
+    let posts = fetch_all_posts(coffee_api, fetch_config).await?;
+    let cached_songs = std::mem::take(&mut index_cache.songs);
+    index_cache.set_posts(posts);
+
+    Ok(ChangeSummary::diff(&cached_songs, &index_cache.songs))
+}
+
+/// Splits a page into posts we haven't indexed yet, recording their IDs in
+/// `known_ids`. Also reports whether the page reached already-indexed posts,
+/// meaning there's no need to look at older pages.
+fn take_new_posts(posts: Vec<RawPost>, known_ids: &mut HashSet<usize>) -> (Vec<RawPost>, bool) {
+    let mut reached_existing_content = false;
+
+    let new_posts = posts
+        .into_iter()
+        .filter(|post| {
+            let is_new = known_ids.insert(post.id);
+
+            // We've caught up to our index
+            if !is_new {
+                reached_existing_content = true;
+            }
+
+            is_new
+        })
+        .collect();
+
+    (new_posts, reached_existing_content)
+}
+
+/// Walks pages from newest to oldest, collecting posts until we reach ones
+/// that are already indexed. The rest of that page is still checked in case
+/// posts arrived out of order.
+pub async fn find_new_posts(
+    coffee_api: &CoffeeApi,
+    fetch_config: &FetchConfig,
+    index_cache: &IndexCache,
+    show_progress: bool,
+) -> Result<Vec<RawPost>> {
+    let mut known_ids = index_cache.known_post_ids();
+    let mut new_posts = vec![];
+    let mut page_number = 1;
+
+    loop {
+        if show_progress {
+            print!("Fetching page {page_number}...");
+            io::stdout().flush()?;
+        }
+        let response = coffee_api
+            .get_posts(page_number, fetch_config.page_size.max(1))
+            .await?;
+
+        let is_last_page = response.meta.current_page == response.meta.last_page;
+        let (page_posts, reached_existing_content) = take_new_posts(response.data, &mut known_ids);
+        new_posts.extend(page_posts);
+
+        if show_progress {
+            println!(" done!");
+        }
+
+        if reached_existing_content || is_last_page {
+            break;
+        }
+
+        page_number += 1;
+    }
+
+    Ok(new_posts)
+}
+
+/// Adds posts published since the last update.
+pub async fn incremental_update(
+    coffee_api: &CoffeeApi,
+    fetch_config: &FetchConfig,
+    index_cache: &mut IndexCache,
+) -> Result<()> {
+    let new_posts = find_new_posts(coffee_api, fetch_config, index_cache, true).await?;
+    index_cache.extend_posts(new_posts);
+
+    Ok(())
+}
+
+/// Result of `--check`, printed as JSON
+#[derive(Serialize)]
+pub struct CheckReport {
+    pub has_updates: bool,
+    pub new_count: usize,
+    pub new_posts: Vec<NewPost>,
+}
+
+#[derive(Serialize)]
+pub struct NewPost {
+    pub id: usize,
+    /// Missing when the post couldn't be parsed
+    pub heading: Option<String>,
+}
+
+/// Reports which posts an update would add, without touching the cache.
+pub async fn check(
+    coffee_api: &CoffeeApi,
+    fetch_config: &FetchConfig,
+    index_cache: &IndexCache,
+) -> Result<CheckReport> {
+    let new_posts: Vec<_> = find_new_posts(coffee_api, fetch_config, index_cache, false)
+        .await?
+        .iter()
+        .map(|post| NewPost {
+            id: post.id,
+            heading: post.parse().ok().map(|post| post.project_update_heading),
+        })
+        .collect();
+
+    Ok(CheckReport {
+        has_updates: !new_posts.is_empty(),
+        new_count: new_posts.len(),
+        new_posts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        api::coffee_api::PageMeta,
+        models::{sequence_number::SequenceNumber, song::Difficulty},
+        test_support::{self, PostJson},
+    };
+
+    use super::*;
+
+    fn post(id: usize, heading: &str) -> RawPost {
+        PostJson::new(id, heading).raw()
+    }
+
+    fn page(current_page: usize, ids: &[usize]) -> PageResponse<RawPost> {
+        PageResponse {
+            data: ids
+                .iter()
+                .map(|&id| post(id, &format!("Song {id} - Artist | #{id}")))
+                .collect(),
+            meta: PageMeta {
+                current_page,
+                last_page: 3,
+            },
+        }
+    }
+
+    fn song(id: usize, title: &str, difficulty: Difficulty) -> Song {
+        Song {
+            id,
+            artist: "Artist".to_owned(),
+            title: title.to_owned(),
+            difficulty,
+            link: format!("https://example.com/{id}"),
+            sequence_number: Some(SequenceNumber::from(u32::try_from(id).unwrap())),
+            ..Song::default()
+        }
+    }
+
+    #[test]
+    fn test_resync_diff() {
+        let cached = vec![
+            song(1, "Unchanged", Difficulty::Beginner),
+            song(2, "Typo", Difficulty::Unrated),
+            song(3, "Deleted", Difficulty::Master),
+        ];
+        let fetched = vec![
+            song(4, "Brand New", Difficulty::Advanced),
+            song(2, "Fixed", Difficulty::Expert),
+            song(1, "Unchanged", Difficulty::Beginner),
+        ];
+
+        let summary = ChangeSummary::diff(&cached, &fetched);
+
+        let ids = |songs: &[Song]| songs.iter().map(|s| s.id).collect::<Vec<_>>();
+        assert_eq!(ids(&summary.added), vec![4]);
+        assert_eq!(ids(&summary.removed), vec![3]);
+        assert_eq!(summary.updated.len(), 1);
+        assert_eq!(
+            summary.updated[0].changed_fields(),
+            vec!["title", "difficulty"]
+        );
+    }
+
+    #[test]
+    fn test_take_new_posts_ignores_sequence_numbers() {
+        let posts = vec![
+            post(12, "Blank - Artist | "),
+            post(10, "Odd - Artist | #TBD"),
+            post(11, "Older - Artist | #3"),
+            post(5, "Known - Artist | #99"),
+        ];
+        let mut known_ids = HashSet::from([5]);
+
+        let (new_posts, reached_existing_content) = take_new_posts(posts.clone(), &mut known_ids);
+        let ids: Vec<_> = new_posts.iter().map(|post| post.id).collect();
+        assert_eq!(ids, vec![12, 10, 11]);
+        assert!(reached_existing_content);
+
+        // A second pass over the same posts (e.g. a shifted page) adds nothing
+        let (new_posts, _) = take_new_posts(posts, &mut known_ids);
+        assert!(new_posts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_full_index_from_replayed_pages() {
+        let temp_dir = test_support::temp_dir();
+        let dir = temp_dir.path();
+        let write_page = |page_number: usize, posts: serde_json::Value| {
+            let page = serde_json::json!({
+                "data": posts,
+                "meta": { "current_page": page_number, "last_page": 2 },
+            });
+            std::fs::write(
+                dir.join(format!("page-{page_number}-per-2.json")),
+                page.to_string(),
+            )
+            .unwrap();
+        };
+        let tagged_post = |id: usize, heading: &str, category_id: usize| {
+            PostJson::new(id, heading)
+                .with_categories(&[category_id])
+                .with("publish_on", "2024-03-01 18:30:00")
+                .build()
+        };
+        write_page(
+            1,
+            serde_json::json!([
+                tagged_post(4, "Everlong - Foo Fighters | #4", 174_258),
+                tagged_post(3, "Chop Suey! - System Of A Down | #3", 174_259),
+            ]),
+        );
+        write_page(
+            2,
+            serde_json::json!([
+                tagged_post(3, "Chop Suey! - System Of A Down | #3", 174_259),
+                tagged_post(1, "Toxicity - System Of A Down | #1", 174_260),
+            ]),
+        );
+
+        let coffee_api = CoffeeApi::new(&crate::config::Config::default())
+            .unwrap()
+            .with_replay(dir.to_path_buf());
+        let fetch_config = FetchConfig {
+            page_size: 2,
+            concurrency: 2,
+        };
+        let mut index_cache = IndexCache::default();
+        full_index(&coffee_api, &fetch_config, &mut index_cache)
+            .await
+            .unwrap();
+
+        let songs: Vec<_> = index_cache
+            .songs
+            .iter()
+            .map(|s| (s.id, s.artist.as_str(), s.difficulty.clone()))
+            .collect();
+        assert_eq!(
+            songs,
+            vec![
+                (4, "Foo Fighters", Difficulty::Expert),
+                (3, "System of a Down", Difficulty::Master),
+                (1, "System of a Down", Difficulty::Beginner),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_pages_keeps_page_order_and_drops_shifted_duplicates() {
+        let merged = merge_pages(vec![page(1, &[9, 8]), page(2, &[8, 7]), page(3, &[6])]);
+        let ids: Vec<_> = merged.iter().map(|post| post.id).collect();
+        assert_eq!(ids, vec![9, 8, 7, 6]);
+    }
+}
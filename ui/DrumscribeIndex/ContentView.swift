@@ -82,9 +82,9 @@ private struct SongListView: View {
         songs.sorted { a, b in
             switch sortOption {
             case .titleAZ:
-                return a.title.localizedCaseInsensitiveCompare(b.title) == .orderedAscending
+                return a.title.localizedStandardCompare(b.title) == .orderedAscending
             case .recentlyAdded:
-                return (Double(a.sequenceNumber) ?? 0) > (Double(b.sequenceNumber) ?? 0)
+                return a.sequenceSortKey > b.sequenceSortKey
             case .difficultyAsc:
                 return a.difficulty.sortOrder < b.difficulty.sortOrder
             case .difficultyDesc:
@@ -98,9 +98,11 @@ private struct SongListView: View {
     private var filteredSongs: [Song] {
         var result = groups.flatMap(\.songs)
         if !searchText.isEmpty {
+            let query = searchText.foldedKey
             result = result.filter {
-                $0.title.localizedCaseInsensitiveContains(searchText) ||
-                $0.artist.localizedCaseInsensitiveContains(searchText)
+                $0.title.foldedKey.contains(query) ||
+                $0.artist.foldedKey.contains(query) ||
+                ($0.featuredArtists ?? []).contains { $0.foldedKey.contains(query) }
             }
         }
         if !selectedDifficulties.isEmpty {
@@ -113,10 +115,15 @@ private struct SongListView: View {
     }
 
     private var displayGroups: [SongGroup] {
-        let byArtist = Dictionary(grouping: filteredSongs, by: \.artist)
+        // Keep the CLI's artist order, which follows its artist_order setting
+        let position = Dictionary(
+            groups.enumerated().map { ($1.artist.foldedKey, $0) },
+            uniquingKeysWith: { first, _ in first }
+        )
+        let byArtist = Dictionary(grouping: filteredSongs, by: \.artist.foldedKey)
         return byArtist
-            .map { SongGroup(artist: $0.key, songs: sortedSongs($0.value)) }
-            .sorted { $0.artist < $1.artist }
+            .map { SongGroup(artist: $0.value[0].artist, sortName: nil, songs: sortedSongs($0.value)) }
+            .sorted { (position[$0.artist.foldedKey] ?? .max) < (position[$1.artist.foldedKey] ?? .max) }
     }
 
     private var displaySongs: [Song] {
@@ -316,6 +323,11 @@ struct SongRow: View {
         HStack {
             VStack(alignment: .leading, spacing: 1) {
                 Text(song.title)
+                if let featured = song.featuredArtists, !featured.isEmpty {
+                    Text("feat. \(featured.joined(separator: ", "))")
+                        .font(.caption)
+                        .foregroundStyle(.secondary)
+                }
                 if showArtist {
                     Text(song.artist)
                         .font(.caption)
@@ -0,0 +1,20 @@
+import Foundation
+
+/// The CLI's data directory, where the index and favorites are kept
+enum DataDirectory {
+    /// `DRUMSCRIBE_DATA_DIR` if set, which the CLI inherits from the app,
+    /// otherwise the CLI's default under Application Support
+    static var url: URL? {
+        if let dataDir = ProcessInfo.processInfo.environment["DRUMSCRIBE_DATA_DIR"], !dataDir.isEmpty {
+            return URL(filePath: dataDir, directoryHint: .isDirectory)
+        }
+
+        return FileManager.default.urls(for: .applicationSupportDirectory, in: .userDomainMask)
+            .first?
+            .appending(component: "com.xapphire13.drumscribe-index", directoryHint: .isDirectory)
+    }
+
+    static func file(_ name: String) -> URL? {
+        url?.appending(component: name)
+    }
+}
@@ -5,9 +5,7 @@ final class FavoritesStore: ObservableObject {
     @Published private(set) var favoriteIDs: Set<Int> = []
 
     private var saveURL: URL? {
-        FileManager.default.urls(for: .applicationSupportDirectory, in: .userDomainMask)
-            .first?
-            .appending(components: "com.xapphire13.drumscribe-index", "favorites.json")
+        DataDirectory.file("favorites.json")
     }
 
     init() {
@@ -39,23 +39,46 @@ enum SortOption: CaseIterable {
 struct Song: Identifiable, Codable {
     let id: Int
     let artist: String
+    let featuredArtists: [String]?
     let title: String
     let difficulty: Difficulty
     let link: String
     let sequenceNumber: String
 }
 
+extension Song {
+    /// Sequence number as (number, suffix) so "42a" sorts right after "42"
+    var sequenceSortKey: (Int, String) {
+        let digits = sequenceNumber.prefix { $0.isNumber }
+        return (Int(digits) ?? 0, String(sequenceNumber.dropFirst(digits.count)))
+    }
+}
+
+extension String {
+    /// Form used to compare names and titles: case, accents and width are
+    /// ignored and curly quotes and dashes made plain, so "Beyoncé" matches
+    /// "BEYONCE" and "Don’t" matches "Don't"
+    var foldedKey: String {
+        folding(options: [.caseInsensitive, .diacriticInsensitive, .widthInsensitive], locale: nil)
+            .replacingOccurrences(of: "[‘’‚‛′´`]", with: "'", options: .regularExpression)
+            .replacingOccurrences(of: "[“”„‟″«»]", with: "\"", options: .regularExpression)
+            .replacingOccurrences(of: "[‐‑‒–—―−]", with: "-", options: .regularExpression)
+    }
+}
+
 struct SongGroup: Identifiable, Decodable {
     var id: String { artist }
     let artist: String
+    /// Name the CLI sorted the group under, e.g. "Beatles" for "The Beatles"
+    let sortName: String?
     let songs: [Song]
 }
 
 extension [Song] {
     func grouped() -> [SongGroup] {
-        let byArtist = Dictionary(grouping: self, by: \.artist)
-        return byArtist
-            .map { SongGroup(artist: $0.key, songs: $0.value.sorted { (Double($0.sequenceNumber) ?? 0) < (Double($1.sequenceNumber) ?? 0) }) }
-            .sorted { $0.artist < $1.artist }
+        let byArtist = Dictionary(grouping: self, by: \.artist.foldedKey)
+        return byArtist.values
+            .map { SongGroup(artist: $0[0].artist, sortName: nil, songs: $0.sorted { $0.sequenceSortKey < $1.sequenceSortKey }) }
+            .sorted { $0.artist.localizedStandardCompare($1.artist) == .orderedAscending }
     }
 }
@@ -108,20 +108,19 @@ final class SongLoader: ObservableObject {
         }
     }
 
-    private var indexFileURL: URL? {
-        FileManager.default.urls(for: .applicationSupportDirectory, in: .userDomainMask)
-            .first?
-            .appending(components: "com.xapphire13.drumscribe-index", "index.bin")
+    /// The index as kept by either storage backend; switching to SQLite
+    /// leaves the old index.bin behind, so the newer of the two is current
+    private var indexFileURLs: [URL] {
+        ["index.sqlite3", "index.bin"].compactMap { DataDirectory.file($0) }
     }
 
     private func loadLastUpdated() {
-        guard let url = indexFileURL,
-              let attrs = try? FileManager.default.attributesOfItem(atPath: url.path),
-              let date = attrs[.modificationDate] as? Date else {
-            lastUpdated = nil
-            return
-        }
-        lastUpdated = date
+        lastUpdated = indexFileURLs
+            .compactMap { url in
+                let attrs = try? FileManager.default.attributesOfItem(atPath: url.path)
+                return attrs?[.modificationDate] as? Date
+            }
+            .max()
     }
 
     func export(format: ExportFormat, to url: URL) async throws {